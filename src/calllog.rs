@@ -0,0 +1,83 @@
+//! 関数の呼び出しの記録 (break main --log-call、calls main)
+//!
+//! 呼び出しごとに引数のレジスタを記録し、戻りアドレスの一時ブレークポイントで
+//! 戻り値と所要時間を記録する。子プロセスの終了後も参照できる。
+
+use std::time::Duration;
+
+/// 1つの関数について記録する呼び出しの最大数
+pub const MAX_CALLS: usize = 10000;
+
+/// 1回の呼び出し
+struct Call {
+    time: String,                 // runからの経過時間
+    args: [u64; 6],               // rdi, rsi, rdx, rcx, r8, r9
+    depth: usize,                 // 再帰呼び出しの深さ
+    ret: Option<(u64, Duration)>, // 戻り値と所要時間。戻っていない場合はNone
+}
+
+/// 1つの関数の呼び出しの記録
+pub struct CallLog {
+    calls: Vec<Call>,
+    dropped: u64, // 上限を超えたため記録しなかった呼び出しの数
+}
+
+impl CallLog {
+    pub fn new() -> Self {
+        CallLog {
+            calls: Vec::new(),
+            dropped: 0,
+        }
+    }
+
+    /// 呼び出しを記録し、その番号を返す。上限に達している場合はNone
+    pub fn push(&mut self, time: String, args: [u64; 6], depth: usize) -> Option<usize> {
+        if self.calls.len() >= MAX_CALLS {
+            self.dropped += 1;
+            return None;
+        }
+        self.calls.push(Call {
+            time,
+            args,
+            depth,
+            ret: None,
+        });
+        Some(self.calls.len() - 1)
+    }
+
+    /// idx番目の呼び出しの戻り値と所要時間を記録
+    pub fn set_return(&mut self, idx: usize, rax: u64, elapsed: Duration) {
+        if let Some(call) = self.calls.get_mut(idx) {
+            call.ret = Some((rax, elapsed));
+        }
+    }
+
+    /// 記録した呼び出しの数
+    pub fn count(&self) -> usize {
+        self.calls.len()
+    }
+
+    /// calls mainを実行。呼び出しを記録した順に表示
+    pub fn print(&self, name: &str) {
+        for (i, c) in self.calls.iter().enumerate() {
+            let args: Vec<String> = c.args.iter().map(|a| format!("{a:#x}")).collect();
+            let ret = match c.ret {
+                Some((rax, elapsed)) => format!("{rax:#x} ({elapsed:?})"),
+                None => "?".to_string(),
+            };
+            println!(
+                "[{}] +{} {}{name}({}) = {ret}",
+                i + 1,
+                c.time,
+                "  ".repeat(c.depth),
+                args.join(", ")
+            );
+        }
+        if self.dropped > 0 {
+            println!(
+                "<<上限の{MAX_CALLS}回を超えた{}回の呼び出しは記録していません>>",
+                self.dropped
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! デバッガ自身の機能と制限 (info zdbg、zdbg --version --json)
+//!
+//! 起動時に環境を調べ、runやattachの際に子プロセスを対象に再度調べる。
+//! zdbgを呼び出すツールが利用できる機能を判断できるよう、JSONでも出力する。
+
+use crate::selftest;
+use nix::unistd::Pid;
+use std::{fmt::Write, fs};
+
+/// 有効なcargoのfeature。現在はfeatureを定義していない
+const FEATURES: &[&str] = &[];
+
+/// デバッガの機能と制限
+pub struct Capabilities {
+    version: &'static str,
+    arch: &'static str,
+    mem_backend: &'static str, // 子プロセスのメモリの読み書きに使用する方法
+    seize: bool,               // PTRACE_SEIZEでトレースしているか
+    ptrace_scope: Option<String>, // yamaのptrace_scope。yamaが無効な場合はNone
+    proc_fs: bool,             // /procが利用できるか
+    aslr_disabled: Option<bool>, // 子プロセスでASLRが無効か。未実行の場合はNone
+    process_vm_readv: Option<bool>, // process_vm_readvが利用できるか。未実行の場合はNone
+    hw_watchpoints: bool,      // ハードウェアウォッチポイントに対応しているか
+    limits: Vec<(&'static str, u64)>, // 制限の名前と値
+}
+
+impl Capabilities {
+    /// 起動時に環境を調べる
+    pub fn probe() -> Self {
+        let ptrace_scope = fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope")
+            .ok()
+            .map(|s| s.trim().to_string());
+
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION"),
+            arch: std::env::consts::ARCH,
+            mem_backend: "ptrace (PEEKDATA/POKEDATA)",
+            seize: false,
+            ptrace_scope,
+            proc_fs: fs::metadata("/proc/self/maps").is_ok(),
+            aslr_disabled: None,
+            process_vm_readv: None,
+            hw_watchpoints: false,
+            limits: vec![
+                ("max-frames", crate::dbg::MAX_FRAMES as u64),
+                ("medit-page", crate::dbg::MEDIT_PAGE as u64),
+                ("log-max-size", crate::dbg::LOG_MAX_SIZE),
+                ("calls-per-function", crate::calllog::MAX_CALLS as u64),
+                ("recent-events", crate::events::RECENT_MAX as u64),
+                ("history", crate::MAX_HISTORY as u64),
+            ],
+        }
+    }
+
+    /// runやattachの際に、停止している子プロセスpidを対象に調べる
+    pub fn probe_child(&mut self, pid: Pid) {
+        self.aslr_disabled = Some(selftest::no_randomize(pid));
+        self.process_vm_readv = Some(selftest::vm_readv(pid));
+        self.proc_fs = fs::metadata(format!("/proc/{pid}/maps")).is_ok();
+    }
+
+    /// 制限の値を変更 (set log-max-sizeなど)
+    pub fn set_limit(&mut self, name: &str, val: u64) {
+        if let Some((_, v)) = self.limits.iter_mut().find(|(n, _)| *n == name) {
+            *v = val;
+        }
+    }
+
+    /// info zdbgを実行
+    pub fn print(&self) {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        let probed = |b: Option<bool>| b.map_or("未確認 (runかattachで確認)", yes_no);
+        let features = if FEATURES.is_empty() {
+            "なし".to_string()
+        } else {
+            FEATURES.join(", ")
+        };
+
+        println!("version          : {}", self.version);
+        println!("arch             : {}", self.arch);
+        println!("features         : {features}");
+        println!("memory backend   : {}", self.mem_backend);
+        println!("seize            : {}", yes_no(self.seize));
+        println!(
+            "ptrace_scope     : {}",
+            self.ptrace_scope.as_deref().unwrap_or("yama無効")
+        );
+        println!("/proc            : {}", yes_no(self.proc_fs));
+        println!("ASLR disabled    : {}", probed(self.aslr_disabled));
+        println!("process_vm_readv : {}", probed(self.process_vm_readv));
+        println!("hw watchpoints   : {}", yes_no(self.hw_watchpoints));
+        println!("limits:");
+        for (name, val) in self.limits.iter() {
+            println!("  {name:<18} {val}");
+        }
+    }
+
+    /// zdbg --version --jsonの出力。serdeを使用しないため手で組み立てる
+    pub fn to_json(&self) -> String {
+        let opt = |b: Option<bool>| b.map_or("null".to_string(), |b| b.to_string());
+        let features: Vec<String> = FEATURES.iter().map(|f| format!("\"{f}\"")).collect();
+        let scope = self
+            .ptrace_scope
+            .as_ref()
+            .map_or("null".to_string(), |s| format!("\"{s}\""));
+
+        let mut s = String::new();
+        write!(
+            s,
+            "{{\"version\":\"{}\",\"arch\":\"{}\",\"features\":[{}],\"memory_backend\":\"{}\",\
+             \"seize\":{},\"ptrace_scope\":{scope},\"proc\":{},\"aslr_disabled\":{},\
+             \"process_vm_readv\":{},\"hw_watchpoints\":{},\"limits\":{{",
+            self.version,
+            self.arch,
+            features.join(","),
+            self.mem_backend,
+            self.seize,
+            self.proc_fs,
+            opt(self.aslr_disabled),
+            opt(self.process_vm_readv),
+            self.hw_watchpoints,
+        )
+        .unwrap();
+        let limits: Vec<String> = self
+            .limits
+            .iter()
+            .map(|(name, val)| format!("\"{name}\":{val}"))
+            .collect();
+        s.push_str(&limits.join(","));
+        s.push_str("}}");
+        s
+    }
+}
@@ -1,23 +1,232 @@
+use crate::{
+    calllog::CallLog,
+    caps::Capabilities,
+    disas::{self, Syntax},
+    elf::Symbols,
+    events::{Action, Events},
+    expr, fpregs,
+    hitlog::HitLog,
+    layout,
+    output::OutputWatch,
+    repro, selftest, syscall,
+};
 use nix::{
-    libc::user_regs_struct,
+    errno::Errno,
+    fcntl::OFlag,
+    libc::{self, user_regs_struct},
     sys::{
         personality::{self, Persona},
         ptrace,
-        wait::{waitpid, WaitStatus},
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
     },
-    unistd::{execvp, fork, ForkResult, Pid},
+    unistd::{close, dup2, execvp, fork, pipe2, read, write, ForkResult, Pid},
 };
 use std::{
+    collections::{hash_map::Entry, BTreeMap, HashMap},
     error::Error,
     ffi::{c_void, CString},
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::io::RawFd,
+    time::{Duration, Instant},
 };
 
 /// デバッガ内の情報
 pub struct DbgInfo {
     pid: Pid,
-    brk_addr: Option<*mut c_void>, // ブレークポイントのアドレス
-    brk_val: i64,                  // ブレークポイントを設定したメモリの元の値
-    filename: String,              // 実行ファイル
+    breakpoints: Vec<Breakpoint>,                   // ブレークポイント
+    next_brk_id: usize,                             // 次に設定するブレークポイントの番号
+    filename: String,                               // 実行ファイル
+    run_args: Vec<String>, // runで指定した、子プロセスに渡すコマンドライン引数 (argv[1]以降)
+    stop_count: u64,       // runしてからの停止回数
+    run_start: Instant,    // runした時刻
+    watch_rss: Option<u64>, // 監視するRSSの閾値 (MB)
+    rss_exceeded: bool,    // RSSが閾値を超えたことを報告済みか
+    crash_report: bool,    // 致命的なシグナルで停止した際にレポートを表示するか
+    width: Option<usize>,  // 出力幅。Noneの場合は端末幅
+    auto_run: bool,        // 実行中のみ使用可能なコマンドで自動的にrunするか
+    proc_watches: Vec<ProcWatch>, // watch-procの条件
+    maps_snapshots: HashMap<String, Vec<MapEntry>>, // maps snapshotで保存したマッピング
+    patches: Vec<Patch>,   // patchで書き換えた内容 (undo用)
+    symbols: Result<Symbols, String>, // 実行ファイルのシンボル。読み込めない場合は理由
+    lib_symbols: HashMap<String, Symbols>, // 共有ライブラリのパスとシンボル
+    bind_now: bool,        // 子プロセスをLD_BIND_NOW=1で実行するか
+    base: Option<u64>,     // PIEのロードアドレス。PIEでないか、まだ実行していない場合はNone
+    stop_on_entry: bool,   // runした際にエントリポイントで停止するか
+    output_watch: OutputWatch, // stop-on-outputのパターン
+    history: Vec<u64>,     // calcの結果 ($1, $2, ...)
+    conv_vars: HashMap<String, u64>, // set $fooで定義した変数
+    log_max_size: u64,     // ブレークポイントのログの最大サイズ (バイト)
+    attached: bool,        // 実行中のプロセスがattachしたものか
+    delta: Option<DeltaSnapshot>, // delta beginで保存したスナップショット
+    stop_regs: Option<user_regs_struct>, // 最後に停止した時点のレジスタ
+    follow_exec: Option<String>, // 子孫のプロセスがexecした際に切り替える実行ファイルのパターン
+    prev_regs: Option<user_regs_struct>, // その1つ前に停止した時点のレジスタ
+    show_insn: bool,       // 停止時にRIPの命令を表示するか
+    syscall_trace: bool,   // straceでシステムコールを表示するか
+    in_syscall: bool,      // システムコールの入口で停止し、出口での停止を待っているか
+    events: Events,        // イベントごとの動作と最近のイベント
+    call_logs: BTreeMap<String, CallLog>, // --log-callで記録した関数名ごとの呼び出し
+    pending_calls: Vec<PendingCall>, // --log-callで記録し、まだ戻っていない呼び出し
+    descendants: Vec<Descendant>, // follow-exec-nameのために自動的にトレースしている子孫のプロセス
+    catch_signals: Vec<Signal>, // catch signalで停止するシグナル
+    caps: Capabilities,    // デバッガの機能と制限 (info zdbg)
+    skip_ld: bool,         // stepiで動的リンカ内をステップ実行せずにエントリポイントまで実行するか
+    disasm_flavor: Syntax, // disassembleと停止時の命令の表示に使う構文
+    dl_brk: Option<u64>, // 共有ライブラリの読み込みを検出するブレークポイントを設定した_dl_debug_state
+    libs: Vec<String>,   // 読み込まれている共有ライブラリのパス
+}
+
+/// follow-exec-nameのために自動的にトレースしている子孫のプロセス
+struct Descendant {
+    pid: Pid,
+    vfork: bool, // vforkで生成され、execするまで親とメモリを共有しているか
+}
+
+/// --log-callで記録し、まだ戻っていない呼び出し
+struct PendingCall {
+    name: String,   // call_logsのキー
+    idx: usize,     // CallLog内の番号
+    ret_addr: u64,  // 戻りアドレス
+    rsp: u64,       // 戻った時点のRSP。再帰呼び出しを区別するために使用
+    start: Instant, // 呼び出された時刻
+}
+
+/// delta beginで保存したスナップショット
+struct DeltaSnapshot {
+    pid: Pid,
+    run_start: Instant, // 保存した時点のrun_start。再実行を検出するために使用
+    regs: user_regs_struct,
+    mem: Option<(usize, Vec<u8>)>, // 保存したメモリの先頭アドレスと内容
+}
+
+/// patchで書き換えたメモリ
+struct Patch {
+    addr: usize,
+    orig: Vec<u8>, // 書き換える前の値
+    new: Vec<u8>,  // 書き換えた値
+}
+
+/// watch-procで監視する/procの値
+#[derive(Debug, Clone, Copy)]
+enum ProcMetric {
+    FdCount, // オープンしているファイルディスクリプタ数
+    Threads, // スレッド数
+    VmSize,  // 仮想メモリサイズ (kB)
+}
+
+/// 比較演算子
+#[derive(Debug, Clone, Copy)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+/// ブレークポイント
+struct Breakpoint {
+    id: usize,
+    addr: *mut c_void,
+    orig_byte: i64, // 0xccに書き換える前のメモリの値
+    enabled: bool,
+    temporary: bool,         // 一度停止したら削除するか
+    hit_count: u32,          // 停止条件が成立した回数
+    ignore_count: u64,       // 停止せずに無視する残りのヒット数
+    cond: Option<BrkCond>,   // 停止する条件
+    sym: Option<String>,     // シンボルで設定した場合のシンボル名。ifuncの場合は解決した実装も含む
+    internal: bool, // デバッガが内部で使う一時ブレークポイントか。番号は0で一覧には表示しない
+    log: Option<HitLog>, // ヒットを記録するログ。設定されている場合は停止しない
+    nth: Option<(u32, u64)>, // --nthで指定した、数え始めた時点のヒット数と停止するヒット数
+    log_call: bool, // --log-callで呼び出しを記録するか。設定されている場合は停止しない
+}
+
+/// 条件付きブレークポイントの条件。reg op valueが成立した場合のみ停止
+struct BrkCond {
+    reg: String,
+    op: CmpOp,
+    value: u64,
+}
+
+/// /proc/<pid>/mapsの1エントリ
+#[derive(Clone)]
+struct MapEntry {
+    start: u64,
+    end: u64,
+    perms: String,
+    offset: u64,  // ファイル内のオフセット
+    path: String, // 匿名マッピングの場合は空
+}
+
+/// watch-procの条件
+struct ProcWatch {
+    metric: ProcMetric,
+    op: CmpOp,
+    value: u64,
+    triggered: bool, // 条件が成立したことを報告済みか
+}
+
+/// コマンドの情報
+struct CmdInfo {
+    names: &'static [&'static str], // コマンド名と省略記法
+    running: bool,                  // 子プロセスの実行中のみ使用可能か
+}
+
+/// コマンドの一覧
+#[rustfmt::skip]
+const COMMANDS: &[CmdInfo] = &[
+    CmdInfo { names: &["break", "b"], running: false },
+    CmdInfo { names: &["tbreak"], running: false },
+    CmdInfo { names: &["info", "i"], running: false },
+    CmdInfo { names: &["enable"], running: false },
+    CmdInfo { names: &["disable"], running: false },
+    CmdInfo { names: &["delete", "d"], running: false },
+    CmdInfo { names: &["run", "r"], running: false },
+    CmdInfo { names: &["attach"], running: false },
+    CmdInfo { names: &["detach"], running: true },
+    CmdInfo { names: &["continue", "c"], running: true },
+    CmdInfo { names: &["signal"], running: true },
+    CmdInfo { names: &["finish", "fin"], running: true },
+    CmdInfo { names: &["until", "u"], running: true },
+    CmdInfo { names: &["stepi", "s"], running: true },
+    CmdInfo { names: &["nexti", "n"], running: true },
+    CmdInfo { names: &["registers", "regs"], running: true },
+    CmdInfo { names: &["calc"], running: false },
+    CmdInfo { names: &["print", "p"], running: false },
+    CmdInfo { names: &["maps"], running: true },
+    CmdInfo { names: &["patch"], running: true },
+    CmdInfo { names: &["memory", "x", "x/"], running: true },
+    CmdInfo { names: &["write"], running: true },
+    CmdInfo { names: &["medit"], running: true },
+    CmdInfo { names: &["disassemble", "disas", "dis"], running: true },
+    CmdInfo { names: &["backtrace", "bt"], running: true },
+    CmdInfo { names: &["setreg"], running: true },
+    CmdInfo { names: &["delta"], running: true },
+    CmdInfo { names: &["strace"], running: true },
+    CmdInfo { names: &["events"], running: false },
+    CmdInfo { names: &["calls"], running: false },
+    CmdInfo { names: &["catch"], running: false },
+    CmdInfo { names: &["watch-rss"], running: false },
+    CmdInfo { names: &["watch-proc"], running: false },
+    CmdInfo { names: &["ignore"], running: false },
+    CmdInfo { names: &["repro-check"], running: false },
+    CmdInfo { names: &["stop-on-output"], running: false },
+    CmdInfo { names: &["set"], running: false },
+    CmdInfo { names: &["help", "h"], running: false },
+    CmdInfo { names: &["exit"], running: false },
+];
+
+/// コマンド名か省略記法からコマンドの情報を検索
+/// x/4xのように回数を含むコマンドは/までで検索する
+fn find_cmd(name: &str) -> Option<&'static CmdInfo> {
+    let name = match name.find('/') {
+        Some(i) => &name[..=i],
+        None => name,
+    };
+    COMMANDS.iter().find(|c| c.names.contains(&name))
 }
 
 /// デバッガ
@@ -42,296 +251,4508 @@ pub enum State {
 /// RunningとNotRunningで共通の実装
 impl<T> ZDbg<T> {
     /// ブレークポイントのアドレスを設定する関数。子プロセスのメモリ上には反映しない。
-    /// アドレス設定に成功した場合はtrueを返す
-    fn set_break_addr(&mut self, cmd: &[&str]) -> bool {
-        if self.info.brk_addr.is_some() {
+    /// アドレス設定に成功した場合は設定したアドレスを返す
+    /// temporaryがtrueの場合は一時ブレークポイントとする
+    fn set_break_addr(
+        &mut self,
+        cmd: &[&str],
+        temporary: bool,
+        running: bool,
+    ) -> Option<*mut c_void> {
+        let mut cmd = cmd.to_vec();
+        let nth = match take_nth_opt(&mut cmd) {
+            Ok(nth) => nth,
+            Err(e) => {
+                eprintln!("<<{e}\n例：break main --nth 500>>");
+                return None;
+            }
+        };
+        let log_call = match cmd.iter().position(|a| *a == "--log-call") {
+            Some(i) => {
+                cmd.remove(i);
+                true
+            }
+            None => false,
+        };
+        let cmd = &cmd[..];
+        let (addr, sym) = self.parse_break_addr(cmd.get(1).copied(), running)?;
+
+        // break 0x8000 if rdi == 0x20 log hits.log $rdi $rsi
+        let (cond_args, log_args) = match cmd.iter().position(|a| *a == "log") {
+            Some(i) => (&cmd[2..i], Some(&cmd[i + 1..])),
+            None => (&cmd[2..], None),
+        };
+
+        let cond = match cond_args.first() {
+            None => None,
+            Some(&"if") => match BrkCond::parse(&cond_args[1..]) {
+                Ok(cond) => Some(cond),
+                Err(e) => {
+                    eprintln!("<<{e}\n例：break 0x8000 if rdi == 0x20>>");
+                    return None;
+                }
+            },
+            Some(_) => {
+                eprintln!("<<引数が不正です\n例：break 0x8000 if rdi == 0x20>>");
+                return None;
+            }
+        };
+
+        let log = match log_args {
+            None => None,
+            Some([path, exprs @ ..]) => Some(HitLog::new(path, exprs)),
+            Some([]) => {
+                eprintln!("<<ログファイルを指定してください\n例：break 0x8000 log hits.log $rdi>>");
+                return None;
+            }
+        };
+
+        // 内部用の一時ブレークポイント (finishの戻りアドレスなど) とは重複してもよい
+        if let Some(b) = self
+            .info
+            .breakpoints
+            .iter_mut()
+            .find(|b| b.addr == addr && !b.internal)
+        {
+            // --nth-from-nowは設定済みのブレークポイントを現在のヒット数から数え直す
+            if let Some((true, n)) = nth {
+                b.ignore_count = n - 1;
+                b.nth = Some((b.hit_count, n));
+                println!(
+                    "<<ブレークポイント {} は今から{n}回目のヒットで停止します>>",
+                    b.id
+                );
+                return None;
+            }
             eprintln!(
-                "<<ブレークポイントは設定済みです：Addr = {:p}>>",
-                self.info.brk_addr.unwrap()
+                "<<ブレークポイント {} は設定済みです：Addr = {:p}>>",
+                b.id, addr
             );
-            false
-        } else if let Some(addr) = get_break_addr(cmd) {
-            self.info.brk_addr = Some(addr); // ブレークポイントのアドレスを保存
-            true
-        } else {
-            false
+            return None;
         }
+
+        let id = self.info.next_brk_id;
+        self.info.next_brk_id += 1;
+        self.info.breakpoints.push(Breakpoint {
+            id,
+            addr,
+            orig_byte: 0,
+            enabled: true,
+            temporary,
+            hit_count: 0,
+            ignore_count: nth.map_or(0, |(_, n)| n - 1),
+            cond,
+            sym,
+            internal: false,
+            log,
+            nth: nth.map(|(_, n)| (0, n)),
+            log_call,
+        });
+        println!(
+            "<<ブレークポイント {id} を設定しました：Addr = {:p}>>",
+            addr
+        );
+
+        Some(addr)
     }
 
-    /// 共通のコマンドを実行
-    fn do_cmd_common(&self, cmd: &[&str]) {
-        match cmd[0] {
-            "help" | "h" => do_help(),
-            _ => (),
+    /// breakの引数 (16進数のアドレスかシンボル名) からアドレスを取得。不正な場合は理由を表示
+    /// シンボル名で指定した場合は表示用のシンボル名も返す
+    fn parse_break_addr(
+        &mut self,
+        arg: Option<&str>,
+        running: bool,
+    ) -> Option<(*mut c_void, Option<String>)> {
+        match arg {
+            None => {
+                eprintln!("<<アドレスかシンボルを指定してください\n例：break 0x8000, break main>>");
+                None
+            }
+            Some(arg) if arg.starts_with("0x") => Some((get_break_addr(arg)?, None)),
+            Some(name) => {
+                let (addr, sym) = self.lookup_symbol(name, running)?;
+                Some((addr as *mut c_void, Some(sym)))
+            }
         }
     }
-}
 
-/// NotRunning時に呼び出し可能なメソッド
-impl ZDbg<NotRunning> {
-    pub fn new(filename: String) -> Self {
-        ZDbg {
-            info: Box::new(DbgInfo {
-                pid: Pid::from_raw(0),
-                brk_addr: None,
-                brk_val: 0,
-                filename,
-            }),
-            _state: NotRunning,
+    /// シンボル名からブレークポイントのアドレスを検索。見つからない場合は理由を表示
+    /// 実行中の場合は、読み込まれている共有ライブラリも検索する
+    /// 表示用のシンボル名も返す
+    fn lookup_symbol(&mut self, name: &str, running: bool) -> Option<(u64, String)> {
+        let (addr, stripped, imported) = match &self.info.symbols {
+            Ok(symbols) => (
+                symbols.lookup(name),
+                symbols.is_empty(),
+                symbols.got_slot(name).is_some(),
+            ),
+            Err(e) => {
+                eprintln!("<<シンボルを読み込めません：{e}>>");
+                return None;
+            }
+        };
+
+        // 実行中の場合、PIEのシンボルはベースアドレスを加算
+        // 実行前の場合はrun時に変換する
+        if let Some(addr) = addr {
+            let base = if running { self.info.base } else { None };
+            return Some((addr + base.unwrap_or(0), name.to_string()));
         }
-    }
 
-    /// ブレークポイントを設定
-    fn do_break(&mut self, cmd: &[&str]) -> bool {
-        self.set_break_addr(cmd)
+        if running {
+            match self.lookup_lib_symbol(name) {
+                Ok(Some(found)) => return Some(found),
+                Ok(None) => (),
+                Err(e) => {
+                    eprintln!("<<{e}>>");
+                    return None;
+                }
+            }
+        }
+
+        if stripped && !imported {
+            eprintln!("<<シンボルテーブルがありません (strip済みのバイナリです)。アドレスを指定してください>>");
+        } else if imported && !running {
+            eprintln!("<<シンボル '{name}' は共有ライブラリの関数です。runで実行し、ライブラリが読み込まれてから設定してください>>");
+        } else {
+            eprintln!("<<シンボル '{name}' が見つかりません>>");
+        }
+        None
     }
 
-    /// 子プロセスを生成し、成功した場合はRunning状態に遷移
-    fn do_run(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
-        // 子プロセスに渡すコマンドライン引数
-        let args: Vec<CString> = cmd.iter().map(|s| CString::new(*s).unwrap()).collect();
+    /// 読み込まれている共有ライブラリから関数nameを検索し、実行時のアドレスを返す
+    /// ifuncの場合は、実行ファイルのGOTから実際に呼び出される実装のアドレスを返す
+    /// 見つかったが実装を特定できない場合はErr
+    fn lookup_lib_symbol(&mut self, name: &str) -> Result<Option<(u64, String)>, String> {
+        let maps = read_maps(self.info.pid).map_err(|e| e.to_string())?;
+        let exe = exe_path(self.info.pid);
 
-        match unsafe { fork()? } {
-            ForkResult::Child => {
-                // ASLRを無効に
-                let p = personality::get().unwrap();
-                personality::set(p | Persona::ADDR_NO_RANDOMIZE).unwrap();
-                ptrace::traceme().unwrap();
+        let mut paths: Vec<&str> = Vec::new();
+        for m in maps.iter() {
+            if m.path.starts_with('/') && m.path != exe && !paths.contains(&m.path.as_str()) {
+                paths.push(&m.path);
+            }
+        }
 
-                // exec
-                execvp(&CString::new(self.info.filename.as_str()).unwrap(), &args).unwrap();
-                unreachable!();
-            }
-            ForkResult::Parent { child, .. } => match waitpid(child, None)? {
-                WaitStatus::Stopped(..) => {
-                    println!("<<子プロセスの実行に成功しました：PID = {child}>>");
-                    self.info.pid = child;
-                    let mut dbg = ZDbg::<Running> {
-                        info: self.info,
-                        _state: Running,
-                    };
-                    dbg.set_break()?; // ブレークポイントを設定
-                    dbg.do_continue()
-                }
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                    Err("子プロセスの実行に失敗しました".into())
-                }
-                _ => Err("子プロセスが不正な状態です".into()),
-            },
+        for path in paths {
+            let lib = match self.info.lib_symbols.entry(path.to_string()) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => match Symbols::load(path) {
+                    Ok(symbols) => e.insert(symbols),
+                    Err(_) => continue,
+                },
+            };
+
+            let sym = match lib.find(name) {
+                Some(sym) => sym,
+                None => continue,
+            };
+
+            let base = load_base(&maps, path).unwrap_or(0);
+            if !sym.ifunc {
+                return Ok(Some((base + sym.addr, name.to_string())));
+            }
+
+            // ifuncの場合、シンボルのアドレスは実装を選択するリゾルバのため、
+            // 実行ファイルのGOTに書き込まれた実装のアドレスを読み込む
+            let got = match &self.info.symbols {
+                Ok(symbols) => symbols
+                    .got_slot(name)
+                    .map(|got| got + self.info.base.unwrap_or(0)),
+                Err(_) => None,
+            };
+            let got = got.ok_or(format!(
+                "{name}はifuncですが、実行ファイルから呼び出されていないため実装を特定できません"
+            ))?;
+
+            let target = ptrace::read(self.info.pid, got as *mut c_void)
+                .map_err(|e| format!("GOTを読み込めません：{e}"))? as u64;
+            let bound = maps
+                .iter()
+                .any(|m| m.path == path && m.start <= target && target < m.end);
+            if !bound {
+                return Err(format!(
+                    "{name}はifuncのため、実際に呼び出される実装がまだ解決されていません\nset bind-now onの後にrunし直すと解決できます"
+                ));
+            }
+
+            let resolved = match lib.symbol_at(target - base) {
+                Some((s, 0)) => s.name.clone(),
+                Some((s, off)) => format!("{}+{off:#x}", s.name),
+                // ライブラリが.symtabを持たない場合はローカルな実装の名前は分からない
+                None => format!(
+                    "{}+{:#x}",
+                    path.rsplit('/').next().unwrap_or(path),
+                    target - base
+                ),
+            };
+            println!("<<{name}はifuncです。実装として{resolved}が選択されています>>");
+            return Ok(Some((target, format!("{name} -> {resolved}"))));
         }
+
+        Ok(None)
     }
 
-    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
-        if cmd.is_empty() {
-            return Ok(State::NotRunning(self));
+    /// ユーザが設定したブレークポイント
+    fn user_breaks(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.info.breakpoints.iter().filter(|b| !b.internal)
+    }
+
+    /// 番号からユーザが設定したブレークポイントを検索
+    fn find_user_break(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.info
+            .breakpoints
+            .iter_mut()
+            .find(|b| !b.internal && b.id == id)
+    }
+
+    /// ブレークポイントの情報を削除。子プロセスのメモリ上には反映しない
+    fn remove_break(&mut self, id: usize) {
+        self.info.breakpoints.retain(|b| b.internal || b.id != id);
+    }
+
+    /// addrに設定された有効なブレークポイント
+    fn enabled_break(&self, addr: usize) -> Option<&Breakpoint> {
+        self.info
+            .breakpoints
+            .iter()
+            .find(|b| b.enabled && b.addr as usize == addr)
+    }
+
+    /// addrに有効なブレークポイントが複数あるか (ユーザと内部用のブレークポイントが重なっている場合)
+    /// 1つを削除しても、他のブレークポイントのために0xccを残す必要がある
+    fn is_shared_break(&self, addr: *mut c_void) -> bool {
+        self.info
+            .breakpoints
+            .iter()
+            .filter(|b| b.enabled && b.addr == addr)
+            .count()
+            > 1
+    }
+
+    /// info breakを実行。runningの場合は子プロセスのメモリに0xccが設定されているかも表示
+    /// patchで書き換えたアドレスの場合は、元の値がパッチのものであることをpatched=1のように表示
+    /// 出力幅に収まらない項目は次の行に折り返す
+    fn do_info_break(&self, running: bool) {
+        if self.user_breaks().next().is_none() {
+            println!("<<ブレークポイントはありません>>");
+            return;
         }
 
-        match cmd[0] {
-            "run" | "r" => return self.do_run(cmd),
-            "break" | "b" => {
-                self.do_break(cmd);
-            }
-            "exit" => return Ok(State::Exit),
-            "continue" | "c" | "stepi" | "s" | "registers" | "regs" => {
-                eprintln!("<<ターゲットを実行していません。runで実行してください>>")
+        let width = self.width();
+        for b in self.user_breaks() {
+            let state = if b.enabled { "enabled" } else { "disabled" };
+            let armed = if !running {
+                ""
+            } else if matches!(ptrace::read(self.info.pid, b.addr), Ok(val) if val & 0xff == 0xcc) {
+                "armed"
+            } else {
+                "not armed"
+            };
+            let temporary = if b.temporary { "temporary" } else { "" };
+            let sym = match &b.sym {
+                Some(sym) => format!("<{sym}>"),
+                None => String::new(),
+            };
+            let cond = match &b.cond {
+                Some(c) => format!("if {c}"),
+                None => String::new(),
+            };
+            let ignore = if b.ignore_count > 0 {
+                format!("ignore={}", b.ignore_count)
+            } else {
+                String::new()
+            };
+            let log = match &b.log {
+                Some(log) => log.describe(),
+                None => String::new(),
+            };
+            let nth = match b.nth {
+                Some((base, n)) => format!("nth={n} (hits={base}から)"),
+                None => String::new(),
+            };
+            let log_call = if b.log_call { "log-call" } else { "" };
+            let patched = match self.patch_at(b.addr as usize) {
+                Some(n) => format!("patched={n}"),
+                None => String::new(),
+            };
+            let parts = [
+                format!("[{}] {:#018x}", b.id, b.addr as usize),
+                format!("{state:<8}"),
+                format!("hits={}", b.hit_count),
+                sym,
+                ignore,
+                nth,
+                temporary.to_string(),
+                cond,
+                log,
+                log_call.to_string(),
+                patched,
+                armed.to_string(),
+            ];
+            for line in layout::wrap(&parts, width, 4) {
+                println!("{line}");
             }
-            _ => self.do_cmd_common(cmd),
         }
+    }
 
-        Ok(State::NotRunning(self))
+    /// infoを実行。runningの場合は子プロセスを実行中
+    fn do_info(&self, cmd: &[&str], running: bool) {
+        match cmd.get(1) {
+            Some(&"breakpoints") | Some(&"break") | Some(&"b") => self.do_info_break(running),
+            Some(&"convenience") => self.do_info_convenience(),
+            Some(&"base") => self.do_info_base(running),
+            Some(&"functions") => self.do_info_functions(cmd.get(2).copied()),
+            Some(&"patches") if running => self.do_info_patches(),
+            Some(&"patches") => {
+                eprintln!(
+                    "<<info patchesはターゲットの実行中のみ使用可能です。runで実行してください>>"
+                )
+            }
+            Some(&"events") => self.info.events.print_policy(),
+            Some(&"zdbg") => self.info.caps.print(),
+            Some(&"maps") if running => self.do_info_maps(cmd.get(2).copied()),
+            Some(&"maps") => {
+                eprintln!(
+                    "<<info mapsはターゲットの実行中のみ使用可能です。runで実行してください>>"
+                )
+            }
+            _ => eprintln!("<<不明なコマンドです：{}>>", cmd.join(" ")),
+        }
     }
-}
 
-/// Running時に呼び出し可能なメソッド
-impl ZDbg<Running> {
-    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
-        if cmd.is_empty() {
-            return Ok(State::Running(self));
+    /// info functionsを実行。patternを指定した場合は名前にpatternを含む関数のみ表示
+    fn do_info_functions(&self, pattern: Option<&str>) {
+        let symbols = match &self.info.symbols {
+            Ok(symbols) => symbols,
+            Err(e) => {
+                eprintln!("<<シンボルを読み込めません：{e}>>");
+                return;
+            }
+        };
+
+        if symbols.is_empty() {
+            println!("<<シンボルテーブルがありません (strip済みのバイナリです)>>");
+            return;
         }
 
-        match cmd[0] {
-            "break" | "b" => self.do_break(cmd)?,
-            "continue" | "c" => return self.do_continue(),
-            "registers" | "regs" => {
-                let regs = ptrace::getregs(self.info.pid)?;
-                print_regs(&regs);
+        for sym in symbols
+            .iter()
+            .filter(|s| pattern.is_none_or(|p| s.name.contains(p)))
+        {
+            println!("{:#018x}  {}", sym.addr, sym.name);
+        }
+    }
+
+    /// info mapsを実行。/proc/<pid>/mapsを表示し、出力幅に収まらないパスは次の行に折り返す
+    /// filterを指定した場合は、パーミッションかパスにfilterを含む行のみ表示
+    fn do_info_maps(&self, filter: Option<&str>) {
+        let width = self.width();
+        let path = format!("/proc/{}/maps", self.info.pid);
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("<<{path}を読み込めません：{e}>>");
+                return;
             }
-            "stepi" | "s" => return self.do_stepi(),
-            "run" | "r" => eprintln!("<<既に実行中です>>"),
-            "exit" => {
-                self.do_exit()?;
-                return Ok(State::Exit);
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("<<{path}を読み込めません：{e}>>");
+                    return;
+                }
+            };
+            let matched = match (filter, MapEntry::parse(&line)) {
+                (None, _) => true,
+                (Some(f), Some(m)) => m.perms.contains(f) || m.path.contains(f),
+                (Some(_), None) => false,
+            };
+            if !matched {
+                continue;
+            }
+            // パスの前の空白による桁揃えは保ったまま、パスのみを折り返す
+            let parts = match line.find('/').or_else(|| line.find('[')) {
+                Some(i) => vec![line[..i].trim_end().to_string(), line[i..].to_string()],
+                None => vec![line.clone()],
+            };
+            for l in layout::wrap(&parts, width, 4) {
+                println!("{l}");
             }
-            _ => self.do_cmd_common(cmd),
         }
+    }
 
-        Ok(State::Running(self))
+    /// addrを書き換えたパッチのうち、最後に適用したものの番号 (patch undoで指定する番号)
+    fn patch_at(&self, addr: usize) -> Option<usize> {
+        self.info
+            .patches
+            .iter()
+            .rposition(|p| p.addr <= addr && addr - p.addr < p.new.len())
+            .map(|i| i + 1)
     }
 
-    /// exitを実行。実行中のプロセスはkill
-    fn do_exit(self) -> Result<(), Box<dyn Error>> {
-        loop {
-            ptrace::kill(self.info.pid)?;
-            match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(()),
-                _ => (),
+    /// info patchesを実行
+    fn do_info_patches(&self) {
+        if self.info.patches.is_empty() {
+            println!("<<パッチはありません>>");
+            return;
+        }
+
+        for (i, p) in self.info.patches.iter().enumerate() {
+            println!(
+                "[{}] {:#018x}  {} -> {}",
+                i + 1,
+                p.addr,
+                hex_bytes(&p.orig),
+                hex_bytes(&p.new)
+            );
+        }
+    }
+
+    /// deleteで削除するブレークポイントの番号を取得
+    /// 引数がない場合は確認の上ですべてのブレークポイントを対象とする
+    fn delete_targets(&self, cmd: &[&str]) -> Vec<usize> {
+        if cmd.len() < 2 {
+            if self.user_breaks().next().is_some()
+                && confirm("すべてのブレークポイントを削除しますか？")
+            {
+                return self.user_breaks().map(|b| b.id).collect();
+            }
+            return Vec::new();
+        }
+
+        match cmd[1].parse::<usize>() {
+            Ok(n) if self.user_breaks().any(|b| b.id == n) => vec![n],
+            Ok(n) => {
+                eprintln!("<<ブレークポイント {n} は存在しません>>");
+                Vec::new()
+            }
+            Err(e) => {
+                eprintln!("<<番号変換エラー：{e}>>");
+                Vec::new()
             }
         }
     }
 
-    /// ブレークポイントを実際に設定
-    /// つまり、該当アドレスのメモリを"int 3" = 0xccに設定
-    fn set_break(&mut self) -> Result<(), Box<dyn Error>> {
-        let addr = if let Some(addr) = self.info.brk_addr {
-            addr
-        } else {
-            return Ok(());
+    /// enable/disableで切り替えるブレークポイントのenabledを変更
+    /// 変更した場合はブレークポイントのアドレスを返す
+    fn toggle_break(&mut self, cmd: &[&str], enable: bool) -> Option<*mut c_void> {
+        let id = match cmd.get(1).map(|n| n.parse::<usize>()) {
+            Some(Ok(id)) => id,
+            Some(Err(e)) => {
+                eprintln!("<<番号変換エラー：{e}>>");
+                return None;
+            }
+            None => {
+                eprintln!("<<番号を指定してください\n例：{} 1>>", cmd[0]);
+                return None;
+            }
+        };
+
+        let b = match self.find_user_break(id) {
+            Some(b) => b,
+            None => {
+                eprintln!("<<ブレークポイント {id} は存在しません>>");
+                return None;
+            }
         };
 
-        // TODO:
-        //
-        // addrの位置にブレークポイントを設定せよ
+        if b.enabled == enable {
+            let state = if enable { "有効" } else { "無効" };
+            eprintln!("<<ブレークポイント {id} は既に{state}です>>");
+            return None;
+        }
 
-        Err("TODO".into())
+        b.enabled = enable;
+        Some(b.addr)
     }
 
-    /// breakを実行
-    fn do_break(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
-        if self.set_break_addr(cmd) {
-            self.set_break()?;
+    /// ignoreを実行。ブレークポイントnの次のcount回のヒットでは停止しない
+    fn do_ignore(&mut self, cmd: &[&str]) {
+        if cmd.len() < 3 {
+            eprintln!("<<引数が不足しています\n例：ignore 1 10>>");
+            return;
+        }
+
+        let id = match cmd[1].parse::<usize>() {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("<<番号変換エラー：{e}>>");
+                return;
+            }
+        };
+        let count = match cmd[2].parse::<u64>() {
+            Ok(count) => count,
+            Err(e) => {
+                eprintln!("<<回数変換エラー：{e}>>");
+                return;
+            }
+        };
+
+        match self.find_user_break(id) {
+            Some(b) => {
+                b.ignore_count = count;
+                println!("<<ブレークポイント {id} の次の{count}回のヒットを無視します>>");
+            }
+            None => eprintln!("<<ブレークポイント {id} は存在しません>>"),
         }
-        Ok(())
     }
 
-    /// stepiを実行。機械語レベルで1行実行
-    fn do_stepi(self) -> Result<State, Box<dyn Error>> {
-        // TODO: ここを実装せよ
-        //
-        // 次の実行アドレスがブレークポイントの場合、
-        // 先に、0xccに書き換えたメモリを元に戻す必要がある
-        // また、0xccを元に戻してステップ実行して、再度ブレークポイントを設定する必要がある (step_and_breakを呼び出すとよい)
-        //
-        // 次の実行アドレスがブレークポイントではない場合は、ptrace::stepとwait_childを呼び出すのみでよい
+    /// stop-on-outputを実行。次のrunから有効
+    /// stop-on-output "pattern" [--count N]、stop-on-output clear、引数なしで一覧を表示
+    fn do_stop_on_output(&mut self, cmd: &[&str]) {
+        match cmd.get(1) {
+            None => return self.info.output_watch.print(),
+            Some(&"clear") => return self.info.output_watch.clear(),
+            _ => (),
+        }
 
-        Err("TODO".into())
+        let mut args = cmd[1..].to_vec();
+        let mut count = 1;
+        if let Some(i) = args.iter().position(|a| *a == "--count") {
+            count = match args.get(i + 1).map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => n,
+                _ => {
+                    eprintln!("<<--countには1以上の回数を指定してください>>");
+                    return;
+                }
+            };
+            args.drain(i..i + 2);
+        }
+
+        let pattern = args.join(" ");
+        let pattern = pattern.trim_matches('"');
+        if pattern.is_empty() {
+            eprintln!("<<パターンを指定してください\n例：stop-on-output \"error\" --count 2>>");
+            return;
+        }
+        self.info.output_watch.add(pattern, count);
     }
 
-    /// ブレークポイントで停止していた場合は
-    /// 1ステップ実行しブレークポイントを再設定
-    fn step_and_break(mut self) -> Result<State, Box<dyn Error>> {
-        // TODO: ここを実装せよ
-        //
-        // 停止した位置がブレークポイントの場合、
-        // 1ステップ機械語レベルで実行しwaitpidで待機
-        // その後、再度ブレークポイントを設定
-        //
-        // ブレークポイントでない場合は何もしない
+    /// repro-checkを実行
+    fn do_repro_check(&self, cmd: &[&str]) {
+        let n = match cmd.get(1).map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n >= 2 => n,
+            _ => {
+                eprintln!("<<2以上の実行回数を指定してください\n例：repro-check 5>>");
+                return;
+            }
+        };
 
-        Ok(State::Running(self))
+        if let Err(e) = repro::check(&self.info.filename, n) {
+            eprintln!("<<repro-checkに失敗しました：{e}>>");
+        }
     }
 
-    /// continueを実行
-    fn do_continue(self) -> Result<State, Box<dyn Error>> {
-        // ブレークポイントで停止していた場合は1ステップ実行後再設定
-        match self.step_and_break()? {
-            State::Running(r) => {
-                // 実行再開
-                ptrace::cont(r.info.pid, None)?;
-                r.wait_child()
+    /// catch signal SIGUSR1を実行。引数がない場合はキャッチするシグナルを表示
+    fn do_catch(&mut self, cmd: &[&str]) {
+        match (cmd.get(1), cmd.get(2)) {
+            (None, _) if self.info.catch_signals.is_empty() => {
+                println!("<<キャッチするシグナルはありません>>")
             }
-            n => Ok(n),
+            (None, _) => {
+                for sig in self.info.catch_signals.iter() {
+                    println!("signal {sig}");
+                }
+            }
+            (Some(&"signal"), Some(arg)) => match parse_signal(arg) {
+                Some(sig) if self.info.catch_signals.contains(&sig) => {
+                    eprintln!("<<{sig}はキャッチ済みです>>")
+                }
+                Some(sig) => {
+                    self.info.catch_signals.push(sig);
+                    println!("<<{sig}を受信したら停止します>>");
+                }
+                None => eprintln!("<<不明なシグナルです：{arg}>>"),
+            },
+            _ => eprintln!("<<引数が不正です\n例：catch signal SIGUSR1>>"),
         }
     }
 
-    /// 子プロセスをwait。子プロセスが終了した場合はNotRunning状態に遷移
-    fn wait_child(self) -> Result<State, Box<dyn Error>> {
-        match waitpid(self.info.pid, None)? {
-            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                println!("<<子プロセスが終了しました>>");
-                let not_run = ZDbg::<NotRunning> {
-                    info: self.info,
-                    _state: NotRunning,
-                };
-                Ok(State::NotRunning(not_run))
+    /// delete catch signal SIGUSR1を実行
+    fn do_delete_catch(&mut self, cmd: &[&str]) {
+        let sig = match (cmd.get(2), cmd.get(3)) {
+            (Some(&"signal"), Some(arg)) => match parse_signal(arg) {
+                Some(sig) => sig,
+                None => {
+                    eprintln!("<<不明なシグナルです：{arg}>>");
+                    return;
+                }
+            },
+            _ => {
+                eprintln!("<<引数が不正です\n例：delete catch signal SIGUSR1>>");
+                return;
             }
-            WaitStatus::Stopped(..) => {
-                // TODO: ここを実装せよ
-                //
-                // 停止したアドレスがブレークポイントのアドレスかを調べ
-                // ブレークポイントの場合は以下を行う
-                // - プログラムカウンタを1減らす
-                // - 0xccに書き換えたメモリを元の値に戻す
+        };
 
-                Ok(State::Running(self))
+        match self.info.catch_signals.iter().position(|s| *s == sig) {
+            Some(i) => {
+                self.info.catch_signals.remove(i);
+                println!("<<{sig}のキャッチを削除しました>>");
             }
-            _ => Err("waitpidの返り値が不正です".into()),
+            None => eprintln!("<<{sig}はキャッチしていません>>"),
         }
     }
-}
 
-/// ヘルプを表示
-fn do_help() {
-    println!(
-        r#"コマンド一覧 (括弧内は省略記法)
-break 0x8000 : ブレークポイントを0x8000番地に設定 (b 0x8000)
-run          : プログラムを実行 (r)
-continue     : プログラムを再開 (c)
-stepi        : 機械語レベルで1ステップ実行 (s)
-registers    : レジスタを表示 (regs)
-exit         : 終了
-help         : このヘルプを表示 (h)"#
-    );
-}
+    /// calls mainを実行。--log-callで記録した呼び出しを表示
+    /// 関数名を省略した場合は、記録している関数と呼び出しの数を表示
+    fn do_calls(&self, cmd: &[&str]) {
+        match cmd.get(1) {
+            None if self.info.call_logs.is_empty() => {
+                println!("<<記録した呼び出しはありません\n例：break main --log-call>>")
+            }
+            None => {
+                for (name, log) in self.info.call_logs.iter() {
+                    println!("{name}: {}回", log.count());
+                }
+            }
+            Some(name) => match self.info.call_logs.get(*name) {
+                Some(log) => log.print(name),
+                None => eprintln!("<<{name}の呼び出しは記録していません>>"),
+            },
+        }
+    }
 
-/// レジスタを表示
-fn print_regs(regs: &user_regs_struct) {
-    println!(
-        r#"RIP: {:#016x}, RSP: {:#016x}, RBP: {:#016x}
-RAX: {:#016x}, RBX: {:#016x}, RCX: {:#016x}
-RDX: {:#016x}, RSI: {:#016x}, RDI: {:#016x}
- R8: {:#016x},  R9: {:#016x}, R10: {:#016x}
-R11: {:#016x}, R12: {:#016x}, R13: {:#016x}
-R14: {:#016x}, R15: {:#016x}"#,
-        regs.rip,
-        regs.rsp,
-        regs.rbp,
-        regs.rax,
-        regs.rbx,
-        regs.rcx,
-        regs.rdx,
-        regs.rsi,
-        regs.rdi,
-        regs.r8,
-        regs.r9,
-        regs.r10,
-        regs.r11,
-        regs.r12,
-        regs.r13,
-        regs.r14,
-        regs.r15,
-    );
-}
+    /// events recent 20を実行。最近のイベントを表示
+    fn do_events(&self, cmd: &[&str]) {
+        if cmd.get(1) != Some(&"recent") {
+            eprintln!("<<recentを指定してください\n例：events recent 20>>");
+            return;
+        }
+        match cmd.get(2).map(|n| n.parse::<usize>()) {
+            None => self.info.events.print_recent(20),
+            Some(Ok(n)) if n > 0 => self.info.events.print_recent(n),
+            _ => eprintln!("<<1以上の個数を指定してください：{}>>", cmd[2]),
+        }
+    }
 
-/// コマンドからブレークポイントを計算
-fn get_break_addr(cmd: &[&str]) -> Option<*mut c_void> {
-    if cmd.len() < 2 {
-        eprintln!("<<アドレスを指定してください\n例：break 0x8000>>");
-        return None;
+    /// イベントを記録し、set eventで設定された動作を返す
+    fn event(&mut self, class: &str, detail: String) -> Action {
+        let time = format_elapsed(self.info.run_start.elapsed());
+        self.info.events.dispatch(time, class, detail)
     }
 
-    let addr_str = cmd[1];
-    if &addr_str[0..2] != "0x" {
-        eprintln!("<<アドレスは16進数でのみ指定可能です\n例：break 0x8000>>");
-        return None;
+    /// 停止回数とrunからの経過時間を付けて停止メッセージを表示
+    fn print_stop_banner(&mut self, msg: &str) {
+        self.info.stop_count += 1;
+        println!(
+            "[stop #{} +{}] {msg}",
+            self.info.stop_count,
+            format_elapsed(self.info.run_start.elapsed())
+        );
     }
 
-    let addr = match usize::from_str_radix(&addr_str[2..], 16) {
-        Ok(addr) => addr,
-        Err(e) => {
-            eprintln!("<<アドレス変換エラー：{}>>", e);
-            return None;
+    /// watch-rssを実行。引数がない場合は現在の設定を表示
+    fn do_watch_rss(&mut self, cmd: &[&str]) {
+        if cmd.len() < 2 {
+            match self.info.watch_rss {
+                Some(mb) => println!("<<RSSの閾値：{mb} MB>>"),
+                None => println!("<<RSSは監視していません>>"),
+            }
+            return;
         }
-    } as *mut c_void;
 
-    Some(addr)
+        if cmd[1] == "off" {
+            self.info.watch_rss = None;
+            return;
+        }
+
+        match cmd[1].parse::<u64>() {
+            Ok(mb) => {
+                self.info.watch_rss = Some(mb);
+                self.info.rss_exceeded = false;
+            }
+            Err(e) => eprintln!("<<閾値変換エラー：{}>>", e),
+        }
+    }
+
+    /// RSSが閾値を初めて超えた場合に、RSS、大きな匿名マッピング、停止位置を報告
+    fn check_rss(&mut self) {
+        let threshold = match self.info.watch_rss {
+            Some(mb) if !self.info.rss_exceeded => mb,
+            _ => return,
+        };
+
+        let rss = match read_proc_status(self.info.pid, "VmRSS") {
+            Some(kb) => kb,
+            None => return,
+        };
+        if rss <= threshold.saturating_mul(1024) {
+            return;
+        }
+        self.info.rss_exceeded = true;
+
+        println!("<<RSSが閾値を超えました：RSS = {rss} kB (閾値 = {threshold} MB)>>");
+        for m in largest_anon_maps(self.info.pid, 3) {
+            println!("  {m} ({} kB)", (m.end - m.start) / 1024);
+        }
+        if let Ok(regs) = ptrace::getregs(self.info.pid) {
+            println!("  停止位置：RIP = {:#x}", regs.rip);
+        }
+    }
+
+    /// watch-procを実行。引数がない場合は現在の条件を表示
+    fn do_watch_proc(&mut self, cmd: &[&str]) {
+        if cmd.len() < 2 {
+            if self.info.proc_watches.is_empty() {
+                println!("<<watch-procの条件はありません>>");
+            }
+            for w in self.info.proc_watches.iter() {
+                println!("{} {} {}", w.metric, w.op, w.value);
+            }
+            return;
+        }
+
+        if cmd[1] == "clear" {
+            self.info.proc_watches.clear();
+            return;
+        }
+
+        if cmd.len() < 4 {
+            eprintln!("<<引数が不足しています\n例：watch-proc fd-count > 1000>>");
+            return;
+        }
+
+        let metric = match cmd[1] {
+            "fd-count" => ProcMetric::FdCount,
+            "threads" => ProcMetric::Threads,
+            "vmsize" => ProcMetric::VmSize,
+            _ => {
+                eprintln!("<<fd-count、threads、vmsizeのいずれかを指定してください>>");
+                return;
+            }
+        };
+
+        let op = match CmpOp::parse(cmd[2]) {
+            Some(op) => op,
+            None => {
+                eprintln!("<<比較演算子が不正です：{}>>", cmd[2]);
+                return;
+            }
+        };
+
+        match cmd[3].parse::<u64>() {
+            Ok(value) => self.info.proc_watches.push(ProcWatch {
+                metric,
+                op,
+                value,
+                triggered: false,
+            }),
+            Err(e) => eprintln!("<<値変換エラー：{e}>>"),
+        }
+    }
+
+    /// watch-procの条件を評価し、初めて成立した条件を報告
+    fn check_proc_watches(&mut self) {
+        let pid = self.info.pid;
+        let mut triggered = false;
+        for w in self.info.proc_watches.iter_mut().filter(|w| !w.triggered) {
+            let value = match w.metric {
+                ProcMetric::FdCount => fs::read_dir(format!("/proc/{pid}/fd"))
+                    .ok()
+                    .map(|dir| dir.count() as u64),
+                ProcMetric::Threads => read_proc_status(pid, "Threads"),
+                ProcMetric::VmSize => read_proc_status(pid, "VmSize"),
+            };
+
+            if let Some(value) = value {
+                if w.op.eval(value, w.value) {
+                    w.triggered = true;
+                    triggered = true;
+                    println!(
+                        "<<watch-procの条件が成立しました：{} = {value} ({} {})>>",
+                        w.metric, w.op, w.value
+                    );
+                }
+            }
+        }
+
+        if triggered {
+            if let Ok(regs) = ptrace::getregs(pid) {
+                println!("  停止位置：RIP = {:#x}", regs.rip);
+            }
+        }
+    }
+
+    /// 出力幅を取得
+    fn width(&self) -> usize {
+        self.info.width.unwrap_or_else(layout::term_width)
+    }
+
+    /// setを実行
+    fn do_set(&mut self, cmd: &[&str]) {
+        if cmd.len() < 3 {
+            eprintln!("<<引数が不足しています\n例：set crash-report off>>");
+            return;
+        }
+
+        match cmd[1] {
+            "crash-report" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.crash_report = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "auto-run" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.auto_run = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "stop-on-entry" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.stop_on_entry = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "show-instruction" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.show_insn = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "skip-dynamic-linker" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.skip_ld = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "disassembly-flavor" => match Syntax::parse(cmd[2]) {
+                Some(syntax) => self.info.disasm_flavor = syntax,
+                None => eprintln!("<<intelかattを指定してください>>"),
+            },
+            // execした実行ファイルのパスがパターンに一致した場合、そのプロセスに切り替える
+            "follow-exec-name" => match cmd[2] {
+                "off" => self.info.follow_exec = None,
+                pat => self.info.follow_exec = Some(pat.to_string()),
+            },
+            // 次のrunから有効
+            "bind-now" => match parse_on_off(cmd[2]) {
+                Some(on) => self.info.bind_now = on,
+                None => eprintln!("<<onかoffを指定してください>>"),
+            },
+            "log-max-size" => match cmd[2].parse::<u64>() {
+                Ok(mb) if mb > 0 => match mb.checked_mul(1024 * 1024) {
+                    Some(size) => {
+                        self.info.log_max_size = size;
+                        self.info.caps.set_limit("log-max-size", size);
+                    }
+                    None => eprintln!("<<サイズが大きすぎます：{}>>", cmd[2]),
+                },
+                _ => eprintln!("<<1以上のサイズ (MB) を指定してください：{}>>", cmd[2]),
+            },
+            // set event SIGUSR1 ignore
+            "event" => match (cmd.get(3).and_then(|a| Action::parse(a)), cmd[2]) {
+                (Some(action), class) => {
+                    if let Err(e) = self.info.events.set(class, action) {
+                        eprintln!("<<{e}>>");
+                    }
+                }
+                (None, _) => eprintln!(
+                    "<<stop、log、ignoreのいずれかを指定してください\n例：set event SIGUSR1 log>>"
+                ),
+            },
+            // 0の場合は端末幅に戻す
+            "width" => match cmd[2].parse::<usize>() {
+                Ok(0) => self.info.width = None,
+                Ok(w) => self.info.width = Some(w),
+                Err(e) => eprintln!("<<幅変換エラー：{e}>>"),
+            },
+            _ => eprintln!("<<不明な設定項目です：{}>>", cmd[1]),
+        }
+    }
+
+    /// 式を評価。$から始まる変数は、レジスタ、$1などのcalcの結果、$_ (直前の結果)、
+    /// set $fooで定義した変数の順に解決する。レジスタはregsがSomeの場合のみ使用可能
+    /// mainなどのシンボル名は実行ファイルのシンボルから解決する
+    fn eval_expr(&self, expr: &str, regs: Option<&user_regs_struct>) -> Result<u64, String> {
+        let reg = |name: &str| {
+            if !is_reg_name(name) {
+                return None;
+            }
+            Some(match regs {
+                Some(regs) => Ok(reg_value(regs, name).unwrap()),
+                None => Err("レジスタはプログラム実行中のみ使用可能です".to_string()),
+            })
+        };
+        let resolve =
+            |name: &str| expr::lookup_var(name, &reg, &self.info.history, &self.info.conv_vars);
+        // 実行中の場合、PIEのシンボルはベースアドレスを加算
+        let symbol = |name: &str| {
+            let symbols = self
+                .info
+                .symbols
+                .as_ref()
+                .map_err(|e| format!("シンボルを読み込めません：{e}"))?;
+            let addr = symbols
+                .lookup(name)
+                .ok_or(format!("シンボル '{name}' が見つかりません"))?;
+            let base = if regs.is_some() { self.info.base } else { None };
+            Ok(addr + base.unwrap_or(0))
+        };
+
+        expr::eval(expr, &resolve, &symbol)
+    }
+
+    /// valを値の履歴に追加し、その番号 ($1なら1) を返す
+    fn record_value(&mut self, val: u64) -> usize {
+        self.info.history.push(val);
+        self.info.history.len()
+    }
+
+    /// calcを実行。結果を$1などの変数に保存し、16進数、10進数、符号付き10進数、2進数で表示
+    fn do_calc(&mut self, cmd: &[&str], regs: Option<&user_regs_struct>) {
+        if cmd.len() < 2 {
+            eprintln!("<<式を指定してください\n例：calc 0x7ffce000 - $rsp>>");
+            return;
+        }
+
+        match self.eval_expr(&cmd[1..].join(" "), regs) {
+            Ok(val) => {
+                let n = self.record_value(val);
+                println!(
+                    "${n} = {val:#x}\ndec: {val}\nsigned: {}\nbin: {val:#b}",
+                    val as i64
+                );
+            }
+            Err(e) => eprintln!("<<{e}>>"),
+        }
+    }
+
+    /// printを実行。結果を$1などの変数に保存し、16進数で表示
+    fn do_print(&mut self, cmd: &[&str], regs: Option<&user_regs_struct>) {
+        if cmd.len() < 2 {
+            eprintln!("<<式を指定してください\n例：print $rsp + 0x10>>");
+            return;
+        }
+
+        match self.eval_expr(&cmd[1..].join(" "), regs) {
+            Ok(val) => {
+                let n = self.record_value(val);
+                println!("${n} = {val:#x}");
+            }
+            Err(e) => eprintln!("<<{e}>>"),
+        }
+    }
+
+    /// set $foo = 式を実行
+    fn do_set_var(&mut self, cmd: &[&str], regs: Option<&user_regs_struct>) {
+        let assign = cmd[1..].join(" ");
+        let (name, expr) = match assign.split_once('=') {
+            Some((name, expr)) => (name.trim().trim_start_matches('$'), expr),
+            None => {
+                eprintln!("<<値を指定してください\n例：set $base = 0x555555554000>>");
+                return;
+            }
+        };
+
+        if name.is_empty()
+            || name == "_"
+            || name.chars().all(|c| c.is_ascii_digit())
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            eprintln!("<<変数名が不正です：${name}>>");
+            return;
+        }
+        if is_reg_name(name) {
+            eprintln!("<<レジスタ名は変数名に使用できません：${name}>>");
+            return;
+        }
+
+        match self.eval_expr(expr, regs) {
+            Ok(val) => {
+                println!("${name} = {val:#x}");
+                self.info.conv_vars.insert(name.to_string(), val);
+            }
+            Err(e) => eprintln!("<<{e}>>"),
+        }
+    }
+
+    /// info baseを実行。PIEのロードアドレスを表示
+    fn do_info_base(&self, running: bool) {
+        match (&self.info.symbols, self.info.base) {
+            (Err(e), _) => eprintln!("<<実行ファイルを読み込めません：{e}>>"),
+            (Ok(symbols), _) if !symbols.is_pie() => {
+                println!("<<PIEではありません。アドレスはファイル上のアドレスと同じです>>")
+            }
+            (Ok(_), Some(base)) if running => println!("base: {base:#x}"),
+            (Ok(_), _) => println!("<<PIEです。ベースアドレスはrunの後に確定します>>"),
+        }
+    }
+
+    /// info convenienceを実行
+    fn do_info_convenience(&self) {
+        if self.info.conv_vars.is_empty() && self.info.history.is_empty() {
+            println!("<<変数はありません>>");
+            return;
+        }
+
+        let mut vars: Vec<(&String, &u64)> = self.info.conv_vars.iter().collect();
+        vars.sort();
+        for (name, val) in vars {
+            println!("${name} = {val:#x}");
+        }
+        for (i, val) in self.info.history.iter().enumerate() {
+            println!("${} = {val:#x}", i + 1);
+        }
+    }
+
+    /// 共通のコマンドを実行
+    fn do_cmd_common(&mut self, cmd: &[&str]) {
+        match cmd[0] {
+            "help" | "h" => do_help(),
+            "watch-rss" => self.do_watch_rss(cmd),
+            "watch-proc" => self.do_watch_proc(cmd),
+            "set" => self.do_set(cmd),
+            "ignore" => self.do_ignore(cmd),
+            "repro-check" => self.do_repro_check(cmd),
+            "stop-on-output" => self.do_stop_on_output(cmd),
+            "events" => self.do_events(cmd),
+            "calls" => self.do_calls(cmd),
+            "catch" => self.do_catch(cmd),
+            _ => eprintln!("<<不明なコマンドです：{}>>", cmd.join(" ")),
+        }
+    }
+}
+
+/// NotRunning時に呼び出し可能なメソッド
+impl ZDbg<NotRunning> {
+    pub fn new(filename: String) -> Self {
+        let symbols = Symbols::load(&filename);
+        ZDbg {
+            info: Box::new(DbgInfo {
+                pid: Pid::from_raw(0),
+                breakpoints: Vec::new(),
+                next_brk_id: 1,
+                filename,
+                run_args: Vec::new(),
+                stop_count: 0,
+                run_start: Instant::now(),
+                watch_rss: None,
+                rss_exceeded: false,
+                crash_report: true,
+                width: None,
+                auto_run: false,
+                proc_watches: Vec::new(),
+                maps_snapshots: HashMap::new(),
+                patches: Vec::new(),
+                symbols,
+                lib_symbols: HashMap::new(),
+                bind_now: false,
+                base: None,
+                stop_on_entry: false,
+                output_watch: OutputWatch::new(),
+                history: Vec::new(),
+                conv_vars: HashMap::new(),
+                log_max_size: LOG_MAX_SIZE,
+                attached: false,
+                delta: None,
+                stop_regs: None,
+                follow_exec: None,
+                prev_regs: None,
+                show_insn: true,
+                syscall_trace: false,
+                in_syscall: false,
+                events: Events::new(),
+                call_logs: BTreeMap::new(),
+                pending_calls: Vec::new(),
+                descendants: Vec::new(),
+                catch_signals: Vec::new(),
+                caps: Capabilities::probe(),
+                skip_ld: false,
+                disasm_flavor: Syntax::Intel,
+                dl_brk: None,
+                libs: Vec::new(),
+            }),
+            _state: NotRunning,
+        }
+    }
+
+    /// ブレークポイントを設定
+    fn do_break(&mut self, cmd: &[&str], temporary: bool) -> bool {
+        self.set_break_addr(cmd, temporary, false).is_some()
+    }
+
+    /// runを実行。子プロセスを生成し、ブレークポイントまで実行
+    fn do_run(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        self.info.run_args = cmd[1..].iter().map(|s| s.to_string()).collect();
+        let mut r = match self.start_child()? {
+            State::Running(r) => r,
+            n => return Ok(n),
+        };
+
+        // stop-on-entryが有効な場合はエントリポイントで停止
+        let entry = if r.info.stop_on_entry {
+            r.entry_addr()
+        } else {
+            None
+        };
+        if let Some(entry) = entry {
+            if let Err(e) = r.add_internal_break(entry, None) {
+                eprintln!(
+                    "<<エントリポイントにブレークポイントを設定できません：{entry:#x}：{e}>>"
+                );
+            }
+        }
+
+        match r.do_continue()? {
+            State::Running(r) => {
+                let rip = ptrace::getregs(r.info.pid)?.rip;
+                if Some(rip) == entry {
+                    println!("<<エントリポイントで停止しました：Addr = {rip:#x}>>");
+                }
+                Ok(State::Running(r))
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// 実行中のみ使用可能なコマンドが入力された場合の処理
+    /// auto-runが有効な場合は、確認の上で子プロセスを生成し、mainまで実行してからコマンドを実行
+    /// exec直後は動的リンカの中で停止しているため、mainがない場合はエントリポイントまで実行する
+    fn do_running_cmd(self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        if !self.info.auto_run {
+            eprintln!(
+                "<<{}はターゲットの実行中のみ使用可能です。runで実行してください>>",
+                cmd[0]
+            );
+            return Ok(State::NotRunning(self));
+        }
+
+        if !confirm("ターゲットを実行しますか？") {
+            return Ok(State::NotRunning(self));
+        }
+
+        let mut r = match self.start_child()? {
+            State::Running(r) => r,
+            n => return Ok(n),
+        };
+
+        if let Some(addr) = r.main_addr().or_else(|| r.entry_addr()) {
+            if let Err(e) = r.add_internal_break(addr, None) {
+                eprintln!("<<ブレークポイントを設定できません：{addr:#x}：{e}>>");
+            }
+        }
+        match r.do_continue()? {
+            State::Running(r) => r.do_cmd(cmd),
+            n => Ok(n),
+        }
+    }
+
+    /// 子プロセスを生成し、成功した場合はexec直後の停止状態のままRunning状態に遷移
+    /// コマンドライン引数は、argv[0]を実行ファイル名とし、以降はrun_args
+    fn start_child(self) -> Result<State, Box<dyn Error>> {
+        // 子プロセスに渡すコマンドライン引数
+        let args: Vec<CString> = std::iter::once(&self.info.filename)
+            .chain(self.info.run_args.iter())
+            .map(|s| CString::new(s.as_str()).unwrap())
+            .collect();
+
+        // exec失敗時に子プロセスからerrnoを受け取るパイプ
+        // exec成功時はO_CLOEXECにより閉じられる
+        let (pipe_r, pipe_w) = pipe2(OFlag::O_CLOEXEC)?;
+
+        // stop-on-outputのパターンがある場合は、子プロセスの標準エラー出力をパイプで受け取る
+        let stderr_pipe = if self.info.output_watch.is_empty() {
+            None
+        } else {
+            Some(pipe2(OFlag::O_CLOEXEC)?)
+        };
+
+        match unsafe { fork()? } {
+            ForkResult::Child => {
+                let _ = close(pipe_r);
+                if let Some((err_r, err_w)) = stderr_pipe {
+                    let _ = close(err_r);
+                    if let Err(e) = dup2(err_w, libc::STDERR_FILENO) {
+                        exit_with_errno(pipe_w, ChildStage::Redirect, e);
+                    }
+                }
+
+                // ASLRを無効に
+                // 失敗しても実行は継続し、exec後に親プロセスが検出して警告する
+                if let Ok(p) = personality::get() {
+                    let _ = personality::set(p | Persona::ADDR_NO_RANDOMIZE);
+                }
+                if let Err(e) = ptrace::traceme() {
+                    exit_with_errno(pipe_w, ChildStage::Traceme, e);
+                }
+
+                // 動的リンクを起動時にすべて解決
+                if self.info.bind_now {
+                    std::env::set_var("LD_BIND_NOW", "1");
+                }
+
+                // exec
+                let filename = CString::new(self.info.filename.as_str()).unwrap();
+                let Err(e) = execvp(&filename, &args);
+                exit_with_errno(pipe_w, ChildStage::Exec, e);
+            }
+            ForkResult::Parent { child, .. } => {
+                let _ = close(pipe_w);
+                if let Some((err_r, err_w)) = stderr_pipe {
+                    let _ = close(err_w);
+                    self.info.output_watch.spawn(err_r, child);
+                }
+                let status = waitpid(child, None);
+                let errno = read_errno(pipe_r);
+                let _ = close(pipe_r);
+
+                match status? {
+                    WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                        println!("<<子プロセスの実行に成功しました：PID = {child}>>");
+                        let mut dbg = self.into_running(child, false);
+                        warn_degraded(child);
+                        dbg.relocate_breaks();
+                        dbg.set_break()?; // ブレークポイントを設定
+                        dbg.watch_libraries();
+                        dbg.set_trace_options();
+                        Ok(State::Running(dbg))
+                    }
+                    WaitStatus::Stopped(_, sig) => {
+                        // exec以外の理由で停止した場合は、状態が不明なためkill
+                        eprintln!("<<子プロセスがexec前に停止しました：{sig}>>");
+                        let _ = ptrace::kill(child);
+                        let _ = waitpid(child, None);
+                        Ok(State::NotRunning(self))
+                    }
+                    WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                        match errno {
+                            Some((ChildStage::Traceme, e)) => {
+                                eprintln!("<<PTRACE_TRACEMEに失敗しました：{e}>>");
+                                selftest::print_diagnosis(&["ターゲットの実行とデバッグ"]);
+                            }
+                            Some((ChildStage::Redirect, e)) => {
+                                eprintln!("<<子プロセスの標準エラー出力の設定に失敗しました：{e}>>")
+                            }
+                            Some((ChildStage::Exec, e)) => {
+                                eprintln!("<<子プロセスの実行に失敗しました：{e}>>")
+                            }
+                            None => eprintln!("<<子プロセスの実行に失敗しました>>"),
+                        }
+                        Ok(State::NotRunning(self))
+                    }
+                    _ => Err("子プロセスが不正な状態です".into()),
+                }
+            }
+        }
+    }
+
+    /// 停止しているプロセスpidを対象にRunning状態に遷移。attachedはattachしたプロセスか
+    fn into_running(mut self, pid: Pid, attached: bool) -> ZDbg<Running> {
+        self.info.pid = pid;
+        self.info.attached = attached;
+        self.info.stop_count = 0;
+        self.info.run_start = Instant::now();
+        self.info.rss_exceeded = false;
+        self.info.patches.clear();
+        self.info.stop_regs = None;
+        self.info.prev_regs = None;
+        for w in self.info.proc_watches.iter_mut() {
+            w.triggered = false;
+        }
+        self.info.breakpoints.retain(|b| !b.internal);
+        self.info.dl_brk = None;
+        self.info.libs.clear();
+        self.info.caps.probe_child(pid);
+        ZDbg::<Running> {
+            info: self.info,
+            _state: Running,
+        }
+    }
+
+    /// attachを実行。実行中のプロセスをトレースし、停止させた状態でRunning状態に遷移
+    fn do_attach(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        let pid = match cmd.get(1).map(|p| p.parse::<i32>()) {
+            Some(Ok(pid)) if pid > 0 => Pid::from_raw(pid),
+            _ => {
+                eprintln!("<<PIDを指定してください\n例：attach 1234>>");
+                return Ok(State::NotRunning(self));
+            }
+        };
+
+        match ptrace::attach(pid) {
+            Ok(()) => (),
+            Err(Errno::EPERM) => {
+                eprintln!(
+                    "<<PID {pid}にattachする権限がありません。他のユーザのプロセスか、ptrace_scopeにより制限されています>>"
+                );
+                return Ok(State::NotRunning(self));
+            }
+            Err(Errno::ESRCH) => {
+                eprintln!("<<PID {pid}のプロセスは存在しません>>");
+                return Ok(State::NotRunning(self));
+            }
+            Err(e) => {
+                eprintln!("<<PID {pid}にattachできません：{e}>>");
+                return Ok(State::NotRunning(self));
+            }
+        }
+
+        // PTRACE_ATTACHが送るSIGSTOPで停止するまで待機
+        match waitpid(pid, None)? {
+            WaitStatus::Stopped(..) => (),
+            _ => {
+                eprintln!("<<PID {pid}のプロセスが終了しました>>");
+                return Ok(State::NotRunning(self));
+            }
+        }
+
+        // 実行ファイルが異なる場合はシンボルを読み込み直す
+        let exe = exe_path(pid);
+        if !exe.is_empty() && exe != self.info.filename {
+            println!("<<実行ファイルを{exe}に変更しました>>");
+            self.info.filename = exe;
+            self.info.symbols = Symbols::load(&self.info.filename);
+        }
+
+        println!("<<PID {pid}にattachしました>>");
+        let mut dbg = self.into_running(pid, true);
+        dbg.relocate_breaks();
+        dbg.set_break()?;
+        dbg.watch_libraries();
+        dbg.set_trace_options();
+        Ok(State::Running(dbg))
+    }
+
+    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        if cmd.is_empty() {
+            return Ok(State::NotRunning(self));
+        }
+
+        if find_cmd(cmd[0]).is_some_and(|c| c.running) {
+            return self.do_running_cmd(cmd);
+        }
+
+        match cmd[0] {
+            "run" | "r" => return self.do_run(cmd),
+            "attach" => return self.do_attach(cmd),
+            "break" | "b" => {
+                self.do_break(cmd, false);
+            }
+            "tbreak" => {
+                self.do_break(cmd, true);
+            }
+            "delete" | "d" if cmd.get(1) == Some(&"catch") => self.do_delete_catch(cmd),
+            "delete" | "d" => {
+                for id in self.delete_targets(cmd) {
+                    self.remove_break(id);
+                }
+            }
+            "info" | "i" => self.do_info(cmd, false),
+            "enable" => {
+                self.toggle_break(cmd, true);
+            }
+            "disable" => {
+                self.toggle_break(cmd, false);
+            }
+            "exit" => return Ok(State::Exit),
+            "calc" => self.do_calc(cmd, None),
+            "print" | "p" => self.do_print(cmd, None),
+            "set" if is_var_assign(cmd) => self.do_set_var(cmd, None),
+            _ => self.do_cmd_common(cmd),
+        }
+
+        Ok(State::NotRunning(self))
+    }
+}
+
+/// Running時に呼び出し可能なメソッド
+impl ZDbg<Running> {
+    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        if cmd.is_empty() {
+            return Ok(State::Running(self));
+        }
+
+        match cmd[0] {
+            "break" | "b" => self.do_break(cmd, false)?,
+            "tbreak" => self.do_break(cmd, true)?,
+            "delete" | "d" if cmd.get(1) == Some(&"catch") => self.do_delete_catch(cmd),
+            "delete" | "d" => self.do_delete(cmd)?,
+            "info" | "i" => self.do_info(cmd, true),
+            "enable" => {
+                if let Some(addr) = self.toggle_break(cmd, true) {
+                    self.set_break_at(addr)?;
+                }
+            }
+            "disable" => {
+                if let Some(addr) = self.toggle_break(cmd, false) {
+                    if self.enabled_break(addr as usize).is_none() {
+                        self.unset_break_at(addr)?;
+                    }
+                }
+            }
+            "continue" | "c" => return self.do_continue(),
+            "signal" => return self.do_signal(cmd),
+            "finish" | "fin" => return self.do_finish(),
+            "until" | "u" => return self.do_until(cmd),
+            "detach" => return self.do_detach(),
+            "registers" | "regs" if cmd.get(1) == Some(&"set") => self.do_set_reg(&cmd[2..])?,
+            "registers" | "regs" if cmd.get(1) == Some(&"float") => {
+                match fpregs::get(self.info.pid) {
+                    Ok(fp) => fpregs::print(&fp),
+                    Err(e) => eprintln!("<<浮動小数点レジスタを取得できません：{e}>>"),
+                }
+            }
+            "setreg" => self.do_set_reg(&cmd[1..])?,
+            "set" if cmd.get(1) == Some(&"follow-exec-name") => {
+                self.do_set(cmd);
+                self.set_trace_options();
+                if self.info.follow_exec.is_none() {
+                    self.detach_descendants();
+                }
+            }
+            "delta" => self.do_delta(cmd)?,
+            "strace" => {
+                self.info.syscall_trace = !self.info.syscall_trace;
+                self.info.in_syscall = false;
+                if self.info.syscall_trace {
+                    println!("<<システムコールの表示を開始します。再度straceで停止>>");
+                } else {
+                    println!("<<システムコールの表示を停止しました>>");
+                }
+            }
+            "registers" | "regs" if cmd.get(1) == Some(&"diff") => {
+                let regs = ptrace::getregs(self.info.pid)?;
+                match &self.info.prev_regs {
+                    Some(prev) => {
+                        if !print_reg_diff(prev, &regs) {
+                            println!("<<前回の停止から変化したレジスタはありません>>");
+                        }
+                    }
+                    None => println!("<<前回の停止時のレジスタがありません>>"),
+                }
+            }
+            "registers" | "regs" => {
+                let regs = ptrace::getregs(self.info.pid)?;
+                print_regs(&regs, self.info.prev_regs.as_ref(), self.width());
+            }
+            "stepi" | "s" => return self.do_stepi(cmd),
+            "nexti" | "n" => return self.do_nexti(),
+            "calc" => {
+                let regs = ptrace::getregs(self.info.pid)?;
+                self.do_calc(cmd, Some(&regs));
+            }
+            "print" | "p" => {
+                let regs = ptrace::getregs(self.info.pid)?;
+                self.do_print(cmd, Some(&regs));
+            }
+            "set" if is_var_assign(cmd) => {
+                let regs = ptrace::getregs(self.info.pid)?;
+                self.do_set_var(cmd, Some(&regs));
+            }
+            "maps" => self.do_maps(cmd)?,
+            "patch" => self.do_patch(cmd)?,
+            "memory" => self.do_memory(cmd)?,
+            "x" => self.do_examine(cmd)?,
+            "disassemble" | "disas" | "dis" => self.do_disas(cmd)?,
+            "backtrace" | "bt" => self.backtrace(MAX_FRAMES)?,
+            "write" => self.do_write(cmd)?,
+            "medit" => self.do_medit(cmd)?,
+            x if x.starts_with("x/") => self.do_memory_x(cmd)?,
+            "run" | "r" if self.info.attached => eprintln!(
+                "<<PID {}にattach中のためrunできません。detachかexitの後に実行してください>>",
+                self.info.pid
+            ),
+            "run" | "r" => eprintln!("<<既に実行中です>>"),
+            "attach" => eprintln!("<<既にPID {}をデバッグ中です>>", self.info.pid),
+            "exit" => {
+                self.do_exit()?;
+                return Ok(State::Exit);
+            }
+            _ => self.do_cmd_common(cmd),
+        }
+
+        Ok(State::Running(self))
+    }
+
+    /// maps snapshot/diffを実行
+    fn do_maps(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        if cmd.len() < 3 {
+            eprintln!("<<引数が不足しています\n例：maps snapshot base, maps diff base>>");
+            return Ok(());
+        }
+
+        let maps = read_maps(self.info.pid)?;
+        match cmd[1] {
+            "snapshot" => {
+                println!(
+                    "<<{}個のマッピングを{}として保存しました>>",
+                    maps.len(),
+                    cmd[2]
+                );
+                self.info.maps_snapshots.insert(cmd[2].to_string(), maps);
+            }
+            "diff" => match self.info.maps_snapshots.get(cmd[2]) {
+                Some(old) => print_maps_diff(old, &maps),
+                None => eprintln!("<<スナップショット {} は存在しません>>", cmd[2]),
+            },
+            _ => eprintln!("<<snapshotかdiffを指定してください>>"),
+        }
+
+        Ok(())
+    }
+
+    /// detachを実行。ブレークポイントを元に戻してトレースを終了し、NotRunning状態に遷移
+    /// プロセスはそのまま実行を継続する
+    fn do_detach(mut self) -> Result<State, Box<dyn Error>> {
+        self.detach_descendants();
+        for b in self.info.breakpoints.iter().filter(|b| b.enabled) {
+            self.unset_break_at(b.addr)?;
+        }
+        ptrace::detach(self.info.pid, None)?;
+        println!("<<PID {}からdetachしました>>", self.info.pid);
+
+        let mut not_run = ZDbg::<NotRunning> {
+            info: self.info,
+            _state: NotRunning,
+        };
+        not_run.info.attached = false;
+        Ok(State::NotRunning(not_run))
+    }
+
+    /// exitを実行。実行中のプロセスはkillし、attachしたプロセスはdetach
+    fn do_exit(mut self) -> Result<(), Box<dyn Error>> {
+        if self.info.attached {
+            self.do_detach()?;
+            return Ok(());
+        }
+
+        self.detach_descendants();
+
+        loop {
+            ptrace::kill(self.info.pid)?;
+            match waitpid(self.info.pid, None)? {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(()),
+                _ => (),
+            }
+        }
+    }
+
+    /// PIEの場合はベースアドレスを求め、実行前に設定されたファイル上のアドレスの
+    /// ブレークポイントを実行時のアドレスに変換
+    /// ベースアドレス以上のアドレスは実行時のアドレスとみなし変換しない
+    fn relocate_breaks(&mut self) {
+        let pie = matches!(&self.info.symbols, Ok(symbols) if symbols.is_pie());
+        self.info.base = if pie {
+            read_maps(self.info.pid)
+                .ok()
+                .and_then(|maps| load_base(&maps, &exe_path(self.info.pid)))
+        } else {
+            None
+        };
+
+        let base = match self.info.base {
+            Some(base) => base,
+            None => return,
+        };
+
+        let mut relocated = false;
+        for b in self.info.breakpoints.iter_mut() {
+            if (b.addr as u64) < base {
+                b.addr = (b.addr as u64 + base) as *mut c_void;
+                relocated = true;
+            }
+        }
+        if relocated {
+            println!("<<PIEのため、ブレークポイントのアドレスにベースアドレス {base:#x} を加算しました>>");
+        }
+    }
+
+    /// ブレークポイントを実際に設定
+    /// つまり、すべてのブレークポイントのアドレスのメモリを"int 3" = 0xccに設定
+    /// 設定できないブレークポイントは警告を表示して無視する
+    fn set_break(&mut self) -> Result<(), Box<dyn Error>> {
+        let brks: Vec<(usize, *mut c_void)> = self
+            .info
+            .breakpoints
+            .iter()
+            .filter(|b| b.enabled)
+            .map(|b| (b.id, b.addr))
+            .collect();
+        for (id, addr) in brks {
+            if let Err(e) = self.set_break_at(addr) {
+                eprintln!("<<ブレークポイント {id} を設定できません：Addr = {addr:p}：{e}>>");
+            }
+        }
+        Ok(())
+    }
+
+    /// addrのメモリを"int 3" = 0xccに設定
+    fn set_break_at(&mut self, addr: *mut c_void) -> Result<(), Box<dyn Error>> {
+        // 元の値を保存し、最下位バイトを"int 3"に書き換え
+        // 同じアドレスの別のブレークポイントで0xccを設定済みの場合は、その元の値を引き継ぐ
+        let mut val = ptrace::read(self.info.pid, addr)?;
+        if val & 0xff == 0xcc {
+            if let Some(b) = self
+                .info
+                .breakpoints
+                .iter()
+                .find(|b| b.addr == addr && b.orig_byte != 0 && b.orig_byte & 0xff != 0xcc)
+            {
+                val = b.orig_byte;
+            }
+        }
+        for b in self.info.breakpoints.iter_mut().filter(|b| b.addr == addr) {
+            b.orig_byte = val;
+        }
+        let val_int3 = (val & !0xff) | 0xcc;
+        unsafe { ptrace::write(self.info.pid, addr, val_int3 as *mut c_void)? };
+
+        Ok(())
+    }
+
+    /// addrの0xccを元の値に戻す
+    /// 同じワード内の他のブレークポイントを壊さないよう、最下位バイトのみを戻す
+    fn unset_break_at(&self, addr: *mut c_void) -> Result<(), Box<dyn Error>> {
+        let orig = match self.info.breakpoints.iter().find(|b| b.addr == addr) {
+            Some(b) => b.orig_byte,
+            None => return Ok(()),
+        };
+        let val = ptrace::read(self.info.pid, addr)?;
+        let val_orig = (val & !0xff) | (orig & 0xff);
+        unsafe { ptrace::write(self.info.pid, addr, val_orig as *mut c_void)? };
+
+        Ok(())
+    }
+
+    /// 子プロセスのメモリをaddrからlenバイト読み込む
+    /// 0xccを設定しているブレークポイントのバイトは元の値に置き換える
+    fn read_mem(&self, addr: usize, len: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let end = addr.checked_add(len).ok_or_else(|| {
+            format!("読み込む範囲がアドレス空間を超えています：{addr:#x} + {len:#x}")
+        })?;
+        let start = addr & !7;
+        let mut buf = Vec::with_capacity(len + 16);
+        let mut a = start;
+        while a < end {
+            let word = ptrace::read(self.info.pid, a as *mut c_void)?;
+            buf.extend_from_slice(&word.to_le_bytes());
+            a = match a.checked_add(8) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        let mut buf = buf[addr - start..addr - start + len].to_vec();
+        for b in self.info.breakpoints.iter().filter(|b| b.enabled) {
+            let brk = b.addr as usize;
+            if addr <= brk && brk < end && buf[brk - addr] == 0xcc {
+                buf[brk - addr] = b.orig_byte as u8;
+            }
+        }
+
+        Ok(buf)
+    }
+
+    /// 子プロセスのメモリのaddrにdataを書き込む
+    /// 0xccを設定しているブレークポイントのバイトは、メモリではなく保存した元の値を書き換える
+    fn write_mem(&mut self, addr: usize, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let end = addr.checked_add(data.len()).ok_or_else(|| {
+            format!(
+                "書き込む範囲がアドレス空間を超えています：{addr:#x} + {:#x}",
+                data.len()
+            )
+        })?;
+        let start = addr & !7;
+        for a in (start..end).step_by(8) {
+            let mut bytes = ptrace::read(self.info.pid, a as *mut c_void)?.to_le_bytes();
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                let p = a + i;
+                if p < addr || end <= p {
+                    continue;
+                }
+
+                // ブレークポイントの元の値は常に更新し、
+                // 0xccを設定している場合はメモリを書き換えない
+                let new = data[p - addr];
+                let mut brk = false;
+                for b in self
+                    .info
+                    .breakpoints
+                    .iter_mut()
+                    .filter(|b| b.addr as usize == p)
+                {
+                    b.orig_byte = (b.orig_byte & !0xff) | new as i64;
+                    brk = true;
+                }
+                if brk && *byte == 0xcc {
+                    continue;
+                }
+                *byte = new;
+            }
+
+            let word = i64::from_le_bytes(bytes);
+            unsafe { ptrace::write(self.info.pid, a as *mut c_void, word as *mut c_void)? };
+        }
+
+        Ok(())
+    }
+
+    /// memory read、memory writeを実行
+    fn do_memory(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        if cmd.len() < 4 || (cmd[1] != "read" && cmd[1] != "write") {
+            eprintln!(
+                "<<引数が不足しています\n例：memory read 0x8000 32, memory write 0x8000 0x1234>>"
+            );
+            return Ok(());
+        }
+
+        if cmd[1] == "write" {
+            return self.do_memory_write(cmd[2], cmd[3]);
+        }
+
+        let count = match cmd[3].parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                eprintln!("<<1以上のバイト数を指定してください：{}>>", cmd[3]);
+                return Ok(());
+            }
+        };
+        self.print_words(cmd[2], count)
+    }
+
+    /// memory write 0x8000 0x1234を実行。addr_exprに64ビットの値を書き込む
+    fn do_memory_write(&mut self, addr_expr: &str, val_expr: &str) -> Result<(), Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let (addr, val) = match (
+            self.eval_expr(addr_expr, Some(&regs)),
+            self.eval_expr(val_expr, Some(&regs)),
+        ) {
+            (Ok(addr), Ok(val)) => (addr as usize, val),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+
+        let end = match addr.checked_add(8) {
+            Some(end) => end,
+            None => {
+                eprintln!(
+                    "<<{addr:#x}に書き込めません：書き込む範囲がアドレス空間を超えています：{addr:#x} + 0x8>>"
+                );
+                return Ok(());
+            }
+        };
+        for b in self.info.breakpoints.iter() {
+            let brk = b.addr as usize;
+            if addr <= brk && brk < end {
+                println!(
+                    "<<{brk:#x}にブレークポイントがあるため、0xccの代わりに保存している元の値を更新します>>"
+                );
+            }
+        }
+
+        match self.write_mem(addr, &val.to_le_bytes()) {
+            Ok(()) => println!("<<{addr:#x}に{val:#x}を書き込みました>>"),
+            Err(e) => eprintln!("<<{addr:#x}に書き込めません：{e}>>"),
+        }
+
+        Ok(())
+    }
+
+    /// setreg rax 0x10、registers set rax 0x10を実行。レジスタに値を設定
+    /// argsはレジスタ名と値。レジスタ名の大文字と小文字は区別しない
+    fn do_set_reg(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        if args.len() < 2 {
+            eprintln!("<<引数が不足しています\n例：setreg rax 0x10, registers set rax 0x10>>");
+            return Ok(());
+        }
+
+        let name = args[0].trim_start_matches('$').to_lowercase();
+        let mut regs = ptrace::getregs(self.info.pid)?;
+        let old_rip = regs.rip;
+        let val = match self.eval_expr(args[1], Some(&regs)) {
+            Ok(val) => val,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+
+        match reg_mut(&mut regs, &name) {
+            Some(reg) => *reg = val,
+            None => {
+                eprintln!(
+                    "<<不明なレジスタです：{}\n指定可能なレジスタ：{}>>",
+                    args[0],
+                    REG_NAMES.join(", ")
+                );
+                return Ok(());
+            }
+        }
+
+        // ブレークポイントで停止中にripを移動した場合、停止したブレークポイントは
+        // continueで再設定されなくなるため、ここで0xccを書き戻す
+        // 移動先のブレークポイントはcontinue時にstep_and_breakで通過する
+        if regs.rip != old_rip && self.enabled_break(old_rip as usize).is_some() {
+            let val = ptrace::read(self.info.pid, old_rip as *mut c_void)?;
+            if val & 0xff != 0xcc {
+                self.set_break_at(old_rip as *mut c_void)?;
+            }
+        }
+
+        ptrace::setregs(self.info.pid, regs)?;
+        println!("<<{name}に{val:#x}を設定しました>>");
+
+        Ok(())
+    }
+
+    /// delta begin、delta endを実行
+    fn do_delta(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        match cmd.get(1) {
+            Some(&"begin") => self.do_delta_begin(&cmd[2..]),
+            Some(&"end") => self.do_delta_end(),
+            _ => {
+                eprintln!("<<beginかendを指定してください\n例：delta begin 0x8000 64, delta end>>");
+                Ok(())
+            }
+        }
+    }
+
+    /// delta beginを実行。レジスタと、指定された場合はメモリを保存
+    fn do_delta_begin(&mut self, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let mem = match args {
+            [] => None,
+            [addr, len] => {
+                let addr = match self.eval_expr(addr, Some(&regs)) {
+                    Ok(addr) => addr as usize,
+                    Err(e) => {
+                        eprintln!("<<{e}>>");
+                        return Ok(());
+                    }
+                };
+                let len = match len.parse::<usize>() {
+                    Ok(len) if len > 0 => len,
+                    _ => {
+                        eprintln!("<<1以上のバイト数を指定してください：{len}>>");
+                        return Ok(());
+                    }
+                };
+                match self.read_mem(addr, len) {
+                    Ok(buf) => Some((addr, buf)),
+                    Err(e) => {
+                        eprintln!("<<{addr:#x}を読み込めません：{e}>>");
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                eprintln!("<<アドレスとバイト数を指定してください\n例：delta begin 0x8000 64>>");
+                return Ok(());
+            }
+        };
+
+        match &mem {
+            Some((addr, buf)) => println!(
+                "<<レジスタと{addr:#x}からの{}バイトを保存しました>>",
+                buf.len()
+            ),
+            None => println!("<<レジスタを保存しました>>"),
+        }
+        self.info.delta = Some(DeltaSnapshot {
+            pid: self.info.pid,
+            run_start: self.info.run_start,
+            regs,
+            mem,
+        });
+
+        Ok(())
+    }
+
+    /// delta endを実行。delta beginからのレジスタとメモリの変化を表示
+    fn do_delta_end(&mut self) -> Result<(), Box<dyn Error>> {
+        let snap = match self.info.delta.take() {
+            Some(snap) => snap,
+            None => {
+                eprintln!("<<delta beginを先に実行してください>>");
+                return Ok(());
+            }
+        };
+        if snap.pid != self.info.pid || snap.run_start != self.info.run_start {
+            eprintln!("<<delta beginの後にプロセスが終了または再実行されたため、スナップショットは無効です>>");
+            return Ok(());
+        }
+
+        let regs = ptrace::getregs(self.info.pid)?;
+        let mut changed = print_reg_diff(&snap.regs, &regs);
+
+        if let Some((addr, old)) = &snap.mem {
+            let new = match self.read_mem(*addr, old.len()) {
+                Ok(new) => new,
+                Err(e) => {
+                    eprintln!("<<{addr:#x}を読み込めません：{e}>>");
+                    return Ok(());
+                }
+            };
+
+            // 変化した連続するバイトをまとめて表示
+            let mut i = 0;
+            while i < old.len() {
+                if old[i] == new[i] {
+                    i += 1;
+                    continue;
+                }
+                let start = i;
+                while i < old.len() && old[i] != new[i] {
+                    i += 1;
+                }
+                println!(
+                    "{:#x}: {} → {}",
+                    addr + start,
+                    hex_bytes(&old[start..i]),
+                    hex_bytes(&new[start..i])
+                );
+                changed = true;
+            }
+        }
+
+        if !changed {
+            println!("<<変化はありません>>");
+        }
+
+        Ok(())
+    }
+
+    /// medit 0x8000 128を実行。対話的にメモリを編集し、wでまとめて書き込む
+    /// 編集中はメモリを書き換えず、qか入力の終了で中止した場合は何も書き込まない
+    /// 書き込み不可のマッピングを含む場合は、--forceを指定しない限り書き込む前に確認する
+    fn do_medit(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        let force = cmd.contains(&"--force");
+        let cmd: Vec<&str> = cmd.iter().copied().filter(|a| *a != "--force").collect();
+        if cmd.len() < 2 {
+            eprintln!("<<アドレスを指定してください\n例：medit 0x8000 128>>");
+            return Ok(());
+        }
+
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = match self.resolve_addr(cmd[1], &regs) {
+            Ok(addr) => addr as usize,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+        let len = match cmd.get(2).map(|n| n.parse::<usize>()) {
+            None => MEDIT_PAGE,
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!("<<1以上のバイト数を指定してください：{}>>", cmd[2]);
+                return Ok(());
+            }
+        };
+
+        let end = match addr.checked_add(len) {
+            Some(end) => end as u64,
+            None => {
+                eprintln!("<<範囲がアドレス空間を超えています：{addr:#x} + {len:#x}>>");
+                return Ok(());
+            }
+        };
+
+        // 領域全体がマッピングされているか、書き込み可能かを先に確認
+        let maps = read_maps(self.info.pid)?;
+        let mut a = addr as u64;
+        let mut readonly = false;
+        while a < end {
+            match maps.iter().find(|m| m.start <= a && a < m.end) {
+                Some(m) => {
+                    readonly |= !m.perms.contains('w');
+                    a = m.end;
+                }
+                None => {
+                    eprintln!("<<{a:#x}はマッピングされていません>>");
+                    return Ok(());
+                }
+            }
+        }
+        if readonly {
+            println!("<<書き込み不可のマッピングを含みます。書き込みはptraceで行います>>");
+        }
+
+        let orig = match self.read_mem(addr, len) {
+            Ok(orig) => orig,
+            Err(e) => {
+                eprintln!("<<{addr:#x}を読み込めません：{e}>>");
+                return Ok(());
+            }
+        };
+
+        println!("<<オフセット=値で編集 (例：10=0x41、10=0x4142:2)、n/pでページ移動、wで書き込み、qで中止>>");
+        let pages = len.div_ceil(MEDIT_PAGE);
+        let mut page = 0;
+        let mut edits: BTreeMap<usize, u8> = BTreeMap::new();
+        loop {
+            let start = page * MEDIT_PAGE;
+            let end = (start + MEDIT_PAGE).min(len);
+            let mut buf = orig[start..end].to_vec();
+            for (off, b) in edits.range(start..end) {
+                buf[off - start] = *b;
+            }
+            print_hexdump(addr + start, &buf, self.width());
+
+            print!("medit [{}/{pages}] 編集 {} バイト> ", page + 1, edits.len());
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            if matches!(io::stdin().read_line(&mut line), Ok(0) | Err(_)) {
+                println!("<<編集を中止しました>>");
+                return Ok(());
+            }
+
+            match line.trim() {
+                "" => (),
+                "n" if page + 1 < pages => page += 1,
+                "p" if page > 0 => page -= 1,
+                "n" | "p" => eprintln!("<<これ以上移動できません>>"),
+                "q" => {
+                    println!("<<編集を中止しました>>");
+                    return Ok(());
+                }
+                "w" => break,
+                edit => match parse_medit_edit(edit) {
+                    Ok((off, bytes)) if off.checked_add(bytes.len()).is_some_and(|e| e <= len) => {
+                        for (i, b) in bytes.into_iter().enumerate() {
+                            edits.insert(off + i, b);
+                        }
+                    }
+                    Ok(_) => eprintln!("<<範囲外です：{edit} (長さ {len})>>"),
+                    Err(e) => eprintln!("<<{e}>>"),
+                },
+            }
+        }
+
+        // 最初と最後の編集の間を1回で書き込む
+        let (first, last) = match (edits.keys().next(), edits.keys().next_back()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => {
+                println!("<<編集はありません>>");
+                return Ok(());
+            }
+        };
+        let mut buf = orig[first..=last].to_vec();
+        for (off, b) in edits.iter() {
+            buf[off - first] = *b;
+        }
+        if readonly && !force && !confirm("書き込み不可のマッピングを含みます。書き込みますか？")
+        {
+            println!("<<書き込みを中止しました>>");
+            return Ok(());
+        }
+        match self.write_mem(addr + first, &buf) {
+            Ok(()) => println!(
+                "<<{:#x}から{}バイトを書き込みました>>",
+                addr + first,
+                buf.len()
+            ),
+            Err(e) => eprintln!("<<{:#x}に書き込めません：{e}>>", addr + first),
+        }
+
+        Ok(())
+    }
+
+    /// write 0x8000 de ad be efを実行。バイト列を書き込む
+    fn do_write(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        if cmd.len() < 3 {
+            eprintln!("<<引数が不足しています\n例：write 0x8000 de ad be ef>>");
+            return Ok(());
+        }
+
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = match self.eval_expr(cmd[1], Some(&regs)) {
+            Ok(addr) => addr as usize,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+
+        let bytes: Result<Vec<u8>, _> = cmd[2..]
+            .iter()
+            .map(|b| u8::from_str_radix(b.trim_start_matches("0x"), 16))
+            .collect();
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                eprintln!("<<バイト変換エラー：{e}>>");
+                return Ok(());
+            }
+        };
+
+        match self.write_mem(addr, &bytes) {
+            Ok(()) => println!("<<{addr:#x}に{}バイト書き込みました>>", bytes.len()),
+            Err(e) => eprintln!("<<{addr:#x}に書き込めません：{e}>>"),
+        }
+
+        Ok(())
+    }
+
+    /// x/4x 0x8000を実行。memory read 0x8000 32と同じ
+    fn do_memory_x(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        let count = match cmd[0][2..].strip_suffix('x').map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!("<<x/4xの形式で個数を指定してください：{}>>", cmd[0]);
+                return Ok(());
+            }
+        };
+        if cmd.len() < 2 {
+            eprintln!("<<アドレスを指定してください\n例：x/4x 0x8000>>");
+            return Ok(());
+        }
+        self.print_words(cmd[1], count * 8)
+    }
+
+    /// addr_exprからcountバイトを8バイトずつ、1行に2ワード表示
+    /// 読み込めないワードはエラーを表示して続行する。最初に読み込めたワードを値の履歴に保存する
+    fn print_words(&mut self, addr_expr: &str, count: usize) -> Result<(), Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = match self.eval_expr(addr_expr, Some(&regs)) {
+            Ok(addr) => addr as usize,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+
+        let mut line = String::new();
+        let mut first = None;
+        for i in 0..count.div_ceil(8) {
+            let a = addr + i * 8;
+            match self.read_mem(a, 8) {
+                Ok(bytes) => {
+                    if line.is_empty() {
+                        line = format!("{a:#010x}:");
+                    }
+                    let word = u64::from_le_bytes(bytes.try_into().unwrap());
+                    first.get_or_insert(word);
+                    line.push_str(&format!("  {word:#018x}"));
+                }
+                Err(e) => {
+                    if !line.is_empty() {
+                        println!("{line}");
+                        line.clear();
+                    }
+                    eprintln!("<<{a:#x}を読み込めません：{e}>>");
+                }
+            }
+
+            if i % 2 == 1 && !line.is_empty() {
+                println!("{line}");
+                line.clear();
+            }
+        }
+        if !line.is_empty() {
+            println!("{line}");
+        }
+        if let Some(val) = first {
+            let n = self.record_value(val);
+            println!("${n} = {val:#x}");
+        }
+
+        Ok(())
+    }
+
+    /// x rspやdisas mainのように指定されたアドレスを評価
+    /// $を省略したレジスタ名、式 (実行ファイルの関数名を含む) の順に解決する
+    fn resolve_addr(&self, arg: &str, regs: &user_regs_struct) -> Result<u64, String> {
+        if let Some(val) = reg_value(regs, arg) {
+            return Ok(val);
+        }
+        self.eval_expr(arg, Some(regs))
+    }
+
+    /// 子プロセスのメモリをaddrから最大lenバイト読み込む
+    /// ワード単位で読み込み、失敗した場合はそこまでの内容と、失敗したアドレスとエラーを返す
+    fn read_mem_partial(&self, addr: usize, len: usize) -> (Vec<u8>, Option<(usize, String)>) {
+        let mut buf = Vec::with_capacity(len);
+        while buf.len() < len {
+            let a = addr + buf.len();
+            let n = (8 - a % 8).min(len - buf.len());
+            match self.read_mem(a, n) {
+                Ok(bytes) => buf.extend_from_slice(&bytes),
+                Err(e) => return (buf, Some((a, e.to_string()))),
+            }
+        }
+        (buf, None)
+    }
+
+    /// disas 0x8000 10を実行。アドレスを省略した場合はRIPから、命令数を省略した場合は10命令
+    /// 0xccを設定しているブレークポイントは元の値に戻してから逆アセンブルする
+    fn do_disas(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = match cmd.get(1).map(|arg| self.resolve_addr(arg, &regs)) {
+            None => regs.rip,
+            Some(Ok(addr)) => addr,
+            Some(Err(e)) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+        let count = match cmd.get(2).map(|n| n.parse::<usize>()) {
+            None => 10,
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!("<<1以上の命令数を指定してください：{}>>", cmd[2]);
+                return Ok(());
+            }
+        };
+
+        // x86-64の命令は最長15バイト
+        let (code, err) = self.read_mem_partial(addr as usize, count * 15);
+        let insns = match disas::decode(&code, addr, count, self.info.disasm_flavor) {
+            Ok(insns) => insns,
+            Err(e) => {
+                eprintln!("<<逆アセンブルに失敗しました：{e}>>");
+                return Ok(());
+            }
+        };
+
+        let maps = read_maps(self.info.pid).unwrap_or_default();
+        for insn in insns.iter() {
+            let note = self.insn_note(insn, &regs, &maps);
+            print_insn(insn, regs.rip, &note);
+        }
+
+        // 指定された命令数に満たない場合は理由を表示
+        // 読み込めないワードは、読み込んだ範囲の終わりまで、残りの命令数を上限に1ワードずつ??で表示する
+        if insns.len() < count {
+            let next = insns.last().map_or(addr, |i| i.addr + i.bytes.len() as u64);
+            match err {
+                Some((a, e)) if a as u64 <= next + 15 => {
+                    let end = (addr as usize).saturating_add(count * 15);
+                    let mut w = a;
+                    for _ in insns.len()..count {
+                        if w >= end {
+                            break;
+                        }
+                        let n = 8 - w % 8;
+                        let rip = regs.rip as usize;
+                        let mark = if w <= rip && rip < w + n { "=>" } else { "  " };
+                        let unknown = vec!["??"; n].join(" ");
+                        println!("{mark} {w:#x}:  {unknown}");
+                        w = match w.checked_add(n) {
+                            Some(w) => w,
+                            None => break,
+                        };
+                    }
+                    eprintln!("<<{a:#x}を読み込めません：{e}>>")
+                }
+                _ => eprintln!("<<{next:#x}の命令をデコードできません>>"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 停止した位置の命令を1行で表示
+    /// 逆アセンブルできない場合は読み込めたバイト列のみ表示
+    fn print_stop_insn(&mut self) {
+        let Some(regs) = self.info.stop_regs else {
+            return;
+        };
+        let rip = regs.rip;
+
+        let (code, _) = self.read_mem_partial(rip as usize, 15);
+        match disas::decode(&code, rip, 1, self.info.disasm_flavor).map(|mut v| v.pop()) {
+            Ok(Some(insn)) => {
+                let maps = read_maps(self.info.pid).unwrap_or_default();
+                let note = self.insn_note(&insn, &regs, &maps);
+                print_insn(&insn, rip, &note);
+            }
+            _ if code.is_empty() => println!("=> {rip:#x}:  <<読み込めません>>"),
+            _ => println!("=> {rip:#x}:  {}  (デコードできません)", hex_bytes(&code)),
+        }
+    }
+
+    /// 逆アセンブルした命令の注釈。分岐先のシンボルと、RIPの条件分岐が成立するか
+    /// EFLAGSは停止した時点の値のため、成立するかはRIPの命令についてのみ表示
+    fn insn_note(
+        &mut self,
+        insn: &disas::Insn,
+        regs: &user_regs_struct,
+        maps: &[MapEntry],
+    ) -> String {
+        let mut note = String::new();
+        if let Some(sym) = insn
+            .branch_target()
+            .and_then(|target| self.format_symbol(target, maps))
+        {
+            note.push_str(&format!(" <{sym}>"));
+        }
+        if insn.addr == regs.rip {
+            match disas::jcc_taken(&insn.mnemonic, regs.eflags, regs.rcx) {
+                Some(true) => note.push_str("  ; 分岐する"),
+                Some(false) => note.push_str("  ; 分岐しない"),
+                None => (),
+            }
+        }
+        note
+    }
+
+    /// x 0x8000 64を実行。16バイトごとに16進数とASCIIで表示
+    /// 途中で読み込めなくなった場合は、読み込めたところまで表示してエラーを表示
+    /// 先頭の8バイト (読み込めた分) を値の履歴に保存する
+    fn do_examine(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        if cmd.len() < 2 {
+            eprintln!("<<アドレスを指定してください\n例：x 0x8000 64, x rsp>>");
+            return Ok(());
+        }
+
+        let len = match cmd.get(2).map(|n| n.parse::<usize>()) {
+            None => 64,
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!("<<1以上のバイト数を指定してください：{}>>", cmd[2]);
+                return Ok(());
+            }
+        };
+
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = match self.resolve_addr(cmd[1], &regs) {
+            Ok(addr) => addr as usize,
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                return Ok(());
+            }
+        };
+
+        let (buf, err) = self.read_mem_partial(addr, len);
+        print_hexdump(addr, &buf, self.width());
+        if let Some((a, e)) = err {
+            eprintln!("<<{a:#x}を読み込めません：{e}>>");
+        }
+
+        if !buf.is_empty() {
+            let mut word = [0u8; 8];
+            let n = buf.len().min(8);
+            word[..n].copy_from_slice(&buf[..n]);
+            let val = u64::from_le_bytes(word);
+            let n = self.record_value(val);
+            println!("${n} = {val:#x}");
+        }
+
+        Ok(())
+    }
+
+    /// patchを実行
+    fn do_patch(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        if cmd.len() < 3 {
+            eprintln!(
+                "<<引数が不足しています\n例：patch 0x8000 nop 5, patch 0x8000 jmp 0x8100, patch 0x8000 bytes de ad, patch undo 1>>"
+            );
+            return Ok(());
+        }
+
+        if cmd[1] == "undo" {
+            return self.do_patch_undo(cmd[2]);
+        }
+
+        let addr = match parse_hex(cmd[1]) {
+            Some(addr) => addr as usize,
+            None => return Ok(()),
+        };
+
+        let mut new = match cmd[2] {
+            "nop" => match cmd.get(3).map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => vec![0x90; n],
+                _ => {
+                    eprintln!("<<nopの長さを指定してください\n例：patch 0x8000 nop 5>>");
+                    return Ok(());
+                }
+            },
+            "jmp" => {
+                let target = match cmd.get(3).and_then(|t| parse_hex(t)) {
+                    Some(target) => target,
+                    None => return Ok(()),
+                };
+
+                // jmp rel32 (e9 xx xx xx xx)。相対アドレスは次の命令からのオフセット
+                let rel = target as i64 - (addr as i64 + 5);
+                let rel = match i32::try_from(rel) {
+                    Ok(rel) => rel,
+                    Err(_) => {
+                        eprintln!("<<ジャンプ先が遠すぎます (rel32の範囲外)：{target:#x}>>");
+                        return Ok(());
+                    }
+                };
+
+                let mut code = vec![0xe9];
+                code.extend_from_slice(&rel.to_le_bytes());
+                code
+            }
+            "bytes" => {
+                let bytes: Result<Vec<u8>, _> =
+                    cmd[3..].iter().map(|b| u8::from_str_radix(b, 16)).collect();
+                match bytes {
+                    Ok(bytes) if !bytes.is_empty() => bytes,
+                    Ok(_) => {
+                        eprintln!(
+                            "<<バイト列を指定してください\n例：patch 0x8000 bytes de ad be ef>>"
+                        );
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        eprintln!("<<バイト変換エラー：{e}>>");
+                        return Ok(());
+                    }
+                }
+            }
+            _ => {
+                eprintln!("<<nop、jmp、bytesのいずれかを指定してください>>");
+                return Ok(());
+            }
+        };
+
+        // jmpとbytesは、途中まで書き換えた命令が残らないよう、最後の命令の終わりまでnopで埋める
+        if cmd[2] != "nop" {
+            let (code, _) = self.read_mem_partial(addr, new.len() + 15);
+            match disas::cover_len(&code, addr as u64, new.len()) {
+                Some(len) => new.resize(len, 0x90),
+                None => eprintln!(
+                    "<<{addr:#x}からの命令をデコードできないため、nopで埋めずに書き込みます>>"
+                ),
+            }
+        }
+
+        let orig = match self.read_mem(addr, new.len()) {
+            Ok(orig) => orig,
+            Err(e) => {
+                eprintln!("<<{addr:#x}を読み込めません：{e}>>");
+                return Ok(());
+            }
+        };
+        if let Err(e) = self.write_mem(addr, &new) {
+            eprintln!("<<{addr:#x}に書き込めません：{e}>>");
+            return Ok(());
+        }
+        println!(
+            "<<パッチ {} を適用しました：{:#x}：{} -> {}>>",
+            self.info.patches.len() + 1,
+            addr,
+            hex_bytes(&orig),
+            hex_bytes(&new)
+        );
+        self.info.patches.push(Patch { addr, orig, new });
+
+        Ok(())
+    }
+
+    /// patch undoを実行。元の値に書き戻し、パッチの記録を削除
+    fn do_patch_undo(&mut self, n: &str) -> Result<(), Box<dyn Error>> {
+        let idx = match n.parse::<usize>() {
+            Ok(n) if 1 <= n && n <= self.info.patches.len() => n - 1,
+            _ => {
+                eprintln!("<<パッチ {n} は存在しません>>");
+                return Ok(());
+            }
+        };
+
+        // 書き込みに失敗した場合は、再度undoできるよう記録を残す
+        let (addr, orig) = (
+            self.info.patches[idx].addr,
+            self.info.patches[idx].orig.clone(),
+        );
+        if let Err(e) = self.write_mem(addr, &orig) {
+            eprintln!("<<{addr:#x}に書き込めません：{e}>>");
+            return Ok(());
+        }
+        self.info.patches.remove(idx);
+        println!("<<パッチ {n} を元に戻しました：{addr:#x}>>");
+
+        Ok(())
+    }
+
+    /// 内部用の一時ブレークポイントをaddrに設定。condを指定した場合は成立した場合のみ停止
+    /// 既に有効なブレークポイントがある場合は何もしない
+    fn add_internal_break(
+        &mut self,
+        addr: u64,
+        cond: Option<BrkCond>,
+    ) -> Result<(), Box<dyn Error>> {
+        let addr = addr as *mut c_void;
+        if self.enabled_break(addr as usize).is_some() {
+            return Ok(());
+        }
+
+        self.info.breakpoints.push(Breakpoint {
+            id: 0,
+            addr,
+            orig_byte: 0,
+            enabled: true,
+            temporary: true,
+            hit_count: 0,
+            ignore_count: 0,
+            cond,
+            sym: None,
+            internal: true,
+            log: None,
+            nth: None,
+            log_call: false,
+        });
+        if let Err(e) = self.set_break_at(addr) {
+            self.info
+                .breakpoints
+                .retain(|b| !(b.internal && b.addr == addr));
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// addrの内部用の一時ブレークポイントを、停止せずに不要となった場合に削除
+    fn remove_internal_break(&mut self, addr: u64) -> Result<(), Box<dyn Error>> {
+        let addr = addr as *mut c_void;
+        if self
+            .info
+            .breakpoints
+            .iter()
+            .any(|b| b.internal && b.addr == addr)
+        {
+            if !self.is_shared_break(addr) {
+                self.unset_break_at(addr)?;
+            }
+            self.info
+                .breakpoints
+                .retain(|b| !(b.internal && b.addr == addr));
+        }
+        Ok(())
+    }
+
+    /// finishを実行。戻りアドレスに一時ブレークポイントを設定して実行を再開し、
+    /// 戻った場合はRAXを戻り値として表示
+    fn do_finish(mut self) -> Result<State, Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let slot = self.return_addr_slot(&regs);
+        let ret = match ptrace::read(self.info.pid, slot as *mut c_void) {
+            Ok(ret) => ret as u64,
+            Err(e) => {
+                eprintln!("<<戻りアドレスを読み込めません：{e}>>");
+                return Ok(State::Running(self));
+            }
+        };
+        if let Err(e) = self.add_internal_break(ret, None) {
+            eprintln!("<<戻りアドレス {ret:#x} にブレークポイントを設定できません：{e}>>");
+            return Ok(State::Running(self));
+        }
+
+        match self.do_continue()? {
+            State::Running(mut r) => {
+                let regs = ptrace::getregs(r.info.pid)?;
+                if regs.rip == ret {
+                    println!(
+                        "<<{ret:#x}に戻りました：戻り値 RAX = {:#x} ({})>>",
+                        regs.rax, regs.rax as i64
+                    );
+                } else {
+                    // 戻る前に別の理由で停止した場合は、一時ブレークポイントを削除
+                    r.remove_internal_break(ret)?;
+                }
+                Ok(State::Running(r))
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// until 0x8000を実行。アドレスに一時ブレークポイントを設定して実行を再開
+    /// 先に別のブレークポイントで停止した場合や、終了した場合も一時ブレークポイントは残さない
+    fn do_until(mut self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        let Some((addr, _)) = self.parse_break_addr(cmd.get(1).copied(), true) else {
+            return Ok(State::Running(self));
+        };
+        let addr = addr as u64;
+        if let Err(e) = self.add_internal_break(addr, None) {
+            eprintln!("<<{addr:#x}にブレークポイントを設定できません：{e}>>");
+            return Ok(State::Running(self));
+        }
+
+        match self.do_continue()? {
+            State::Running(mut r) => {
+                let rip = ptrace::getregs(r.info.pid)?.rip;
+                if rip == addr {
+                    println!("<<{addr:#x}に到達しました>>");
+                } else {
+                    r.remove_internal_break(addr)?;
+                    println!("<<{addr:#x}に到達する前に停止しました：RIP = {rip:#x}>>");
+                }
+                Ok(State::Running(r))
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// backtraceを実行。最大limitフレームまで、RBPに保存された呼び出し元のRBPをたどり、各フレームの戻りアドレスを表示
+    /// -fno-omit-frame-pointerでコンパイルされていることが前提で、
+    /// RBPがスタックの外を指す場合や、呼び出し元のRBPが増加していない場合はそこで止める
+    fn backtrace(&mut self, limit: usize) -> Result<(), Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let maps = read_maps(self.info.pid)?;
+        let stack = maps.iter().find(|m| m.path == "[stack]");
+        let in_stack = |addr: u64| {
+            stack.is_some_and(|m| {
+                m.start <= addr && addr.checked_add(16).is_some_and(|e| e <= m.end)
+            })
+        };
+
+        self.print_frame(0, regs.rip, &maps);
+
+        // 関数の先頭 (push rbpの前) ではRBPは呼び出し元のもので、戻りアドレスはスタックの先頭
+        let mut n = 1;
+        let at_entry = matches!(self.symbolize(regs.rip, &maps), Some((_, 0)));
+        if at_entry && in_stack(regs.rsp) {
+            let ret = ptrace::read(self.info.pid, regs.rsp as *mut c_void)? as u64;
+            self.print_frame(n, ret, &maps);
+            n += 1;
+        }
+
+        let mut rbp = regs.rbp;
+        while n < limit && rbp != 0 && in_stack(rbp) {
+            let next = ptrace::read(self.info.pid, rbp as *mut c_void)? as u64;
+            let ret = ptrace::read(self.info.pid, (rbp + 8) as *mut c_void)? as u64;
+            if ret == 0 {
+                break;
+            }
+            self.print_frame(n, ret, &maps);
+            n += 1;
+
+            // スタックは下位アドレスに伸びるため、呼び出し元のRBPは必ず大きい
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+
+        if n == limit {
+            println!("<<{limit}フレームで打ち切りました>>");
+        }
+        Ok(())
+    }
+
+    /// backtraceの1フレームを表示
+    fn print_frame(&mut self, n: usize, addr: u64, maps: &[MapEntry]) {
+        match self.format_symbol(addr, maps) {
+            Some(sym) => println!("#{n:<2} {addr:#018x} <{sym}>"),
+            None => println!("#{n:<2} {addr:#018x}"),
+        }
+    }
+
+    /// addrをmain+0x10のようにシンボルからのオフセットで表した文字列
+    fn format_symbol(&mut self, addr: u64, maps: &[MapEntry]) -> Option<String> {
+        match self.symbolize(addr, maps)? {
+            (name, 0) => Some(name),
+            (name, off) => Some(format!("{name}+{off:#x}")),
+        }
+    }
+
+    /// addrを含む関数のシンボル名と、関数の先頭からのオフセット
+    /// 実行ファイルのシンボルから検索し、見つからない場合はaddrを含む共有ライブラリから検索
+    /// 動的リンカのアドレスはld-linux (_dl_start)のように表し、シンボルがない場合はld-linux (0x1234)とする
+    fn symbolize(&mut self, addr: u64, maps: &[MapEntry]) -> Option<(String, u64)> {
+        if let Ok(symbols) = &self.info.symbols {
+            let base = self.info.base.unwrap_or(0);
+            if let Some((sym, off)) = addr.checked_sub(base).and_then(|a| symbols.symbol_at(a)) {
+                return Some((sym.name.clone(), off));
+            }
+        }
+
+        let path = &maps
+            .iter()
+            .find(|m| m.start <= addr && addr < m.end && m.path.starts_with('/'))?
+            .path;
+        let base = load_base(maps, path)?;
+        let in_ld = interp_range(self.info.pid, maps).is_some_and(|ld| ld.path == *path);
+        let lib = match self.info.lib_symbols.entry(path.clone()) {
+            Entry::Occupied(e) => Some(e.into_mut()),
+            Entry::Vacant(e) => Symbols::load(path).ok().map(|s| e.insert(s)),
+        };
+        let found = lib
+            .and_then(|lib| lib.symbol_at(addr - base))
+            .map(|(sym, off)| (sym.name.clone(), off));
+        match (found, in_ld) {
+            (Some((name, off)), true) => Some((format!("ld-linux ({name})"), off)),
+            (None, true) => Some((format!("ld-linux ({:#x})", addr - base), 0)),
+            (found, false) => found,
+        }
+    }
+
+    /// 戻りアドレスが保存されているスタックのアドレス
+    /// 関数の先頭からRIPまでを逆アセンブルし、push rbpとmov rbp, rspを実行済みかで判断する
+    /// 関数が分からない場合は、フレームが作られているものとしてRBP+8とする
+    fn return_addr_slot(&mut self, regs: &user_regs_struct) -> u64 {
+        let maps = read_maps(self.info.pid).unwrap_or_default();
+        let off = match self.symbolize(regs.rip, &maps) {
+            Some((_, 0)) => return regs.rsp,
+            Some((_, off)) => off,
+            None => return regs.rbp + 8,
+        };
+
+        let start = regs.rip - off;
+        let (code, _) = self.read_mem_partial(start as usize, off as usize);
+        let mut pushed = false;
+        for insn in disas::decode(&code, start, off as usize, Syntax::Intel).unwrap_or_default() {
+            match (insn.mnemonic.as_str(), insn.op_str.as_str()) {
+                ("push", "rbp") => pushed = true,
+                ("mov", "rbp, rsp") => return regs.rbp + 8,
+                _ => (),
+            }
+        }
+
+        // push rbpの後はその分だけ戻りアドレスが下にある
+        if pushed {
+            regs.rsp + 8
+        } else {
+            regs.rsp
+        }
+    }
+
+    /// エントリポイントのアドレス。PIEの場合はベースアドレスを加算
+    fn entry_addr(&self) -> Option<u64> {
+        let entry = self.info.symbols.as_ref().ok()?.entry();
+        Some(entry + self.info.base.unwrap_or(0))
+    }
+
+    /// mainのアドレス。PIEの場合はベースアドレスを加算
+    fn main_addr(&self) -> Option<u64> {
+        let main = self.info.symbols.as_ref().ok()?.lookup("main")?;
+        Some(main + self.info.base.unwrap_or(0))
+    }
+
+    /// 動的リンカの_dl_debug_stateに内部用のブレークポイントを設定し、共有ライブラリの読み込みを検出する
+    /// 動的リンカはライブラリの読み込みと解放の前後で_dl_debug_stateを呼び出す
+    /// 静的リンクされている場合など、_dl_debug_stateが見つからない場合は何もしない
+    fn watch_libraries(&mut self) {
+        self.info.libs = self.loaded_libs();
+        let addr = match self.lookup_lib_symbol("_dl_debug_state") {
+            Ok(Some((addr, _))) => addr,
+            _ => return,
+        };
+
+        let addr = addr as *mut c_void;
+        self.info.breakpoints.push(Breakpoint {
+            id: 0,
+            addr,
+            orig_byte: 0,
+            enabled: true,
+            temporary: false,
+            hit_count: 0,
+            ignore_count: 0,
+            cond: None,
+            sym: None,
+            internal: true,
+            log: None,
+            nth: None,
+            log_call: false,
+        });
+        match self.set_break_at(addr) {
+            Ok(()) => self.info.dl_brk = Some(addr as u64),
+            Err(_) => self
+                .info
+                .breakpoints
+                .retain(|b| !(b.internal && b.addr == addr)),
+        }
+    }
+
+    /// 子プロセスにマッピングされている共有ライブラリ (動的リンカを含む) のパス
+    /// ld.so.cacheなどのデータファイルを除くため、実行可能なマッピングを持つファイルのみとする
+    fn loaded_libs(&self) -> Vec<String> {
+        let exe = exe_path(self.info.pid);
+        let mut libs: Vec<String> = Vec::new();
+        for m in read_maps(self.info.pid).unwrap_or_default() {
+            if m.path.starts_with('/')
+                && m.path != exe
+                && m.perms.contains('x')
+                && !libs.contains(&m.path)
+            {
+                libs.push(m.path);
+            }
+        }
+        libs
+    }
+
+    /// _dl_debug_stateで停止した際に、新たに読み込まれた共有ライブラリをlibraryイベントとして記録
+    /// set event libraryの動作がstopの場合に停止するライブラリを返す
+    fn library_event(&mut self) -> Vec<String> {
+        let libs = self.loaded_libs();
+        let new: Vec<String> = libs
+            .iter()
+            .filter(|l| !self.info.libs.contains(l))
+            .cloned()
+            .collect();
+        self.info.libs = libs;
+
+        let mut stop = Vec::new();
+        for lib in new {
+            match self.event("library", lib.clone()) {
+                Action::Stop => stop.push(lib),
+                Action::Log => println!("<<共有ライブラリを読み込みました：{lib}>>"),
+                Action::Ignore => (),
+            }
+        }
+        stop
+    }
+
+    /// breakを実行。temporaryがtrueの場合はtbreak
+    fn do_break(&mut self, cmd: &[&str], temporary: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(addr) = self.set_break_addr(cmd, temporary, true) {
+            if let Err(e) = self.set_break_at(addr) {
+                eprintln!("<<ブレークポイントを設定できません：Addr = {addr:p}：{e}>>");
+                self.info.breakpoints.retain(|b| b.addr != addr);
+            }
+        }
+        Ok(())
+    }
+
+    /// deleteを実行。削除するブレークポイントのメモリは元の値に戻す
+    fn do_delete(&mut self, cmd: &[&str]) -> Result<(), Box<dyn Error>> {
+        for id in self.delete_targets(cmd) {
+            if let Some(b) = self.user_breaks().find(|b| b.id == id) {
+                if b.enabled && !self.is_shared_break(b.addr) {
+                    self.unset_break_at(b.addr)?;
+                }
+            }
+            self.remove_break(id);
+        }
+        Ok(())
+    }
+
+    /// stepiを実行。機械語レベルで1行実行
+    /// stepi 5のように回数が指定された場合は、各ステップのRIPを表示
+    /// 途中でブレークポイントに到達した場合や、シグナルを受信した場合はそこで止める
+    /// --skip-ldかskip-dynamic-linkerが有効な場合、RIPが動的リンカ内であれば
+    /// ステップ実行せずにエントリポイント (AT_ENTRY) まで実行し、1ステップとして数える
+    fn do_stepi(self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        let skip_ld = self.info.skip_ld || cmd.contains(&"--skip-ld");
+        let cmd: Vec<&str> = cmd.iter().copied().filter(|a| *a != "--skip-ld").collect();
+        let count = match cmd.get(1).map(|n| n.parse::<u64>()) {
+            None => 1,
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!(
+                    "<<1以上の回数を指定してください：{}\n例：stepi 50>>",
+                    cmd[1]
+                );
+                return Ok(State::Running(self));
+            }
+        };
+
+        let mut dbg = self;
+        let mut done = 0;
+        for i in 0..count {
+            let entry = if skip_ld { dbg.ld_skip_target() } else { None };
+            let step = match entry {
+                Some(entry) => dbg.run_to_entry(entry)?,
+                None => dbg.step_once()?,
+            };
+            dbg = match step {
+                State::Running(r) => r,
+                n => return Ok(n),
+            };
+            done += 1;
+            if count == 1 {
+                break;
+            }
+
+            // エントリポイントの前にブレークポイントなどで停止した場合は報告済み
+            if entry.is_some_and(|e| ptrace::getregs(dbg.info.pid).map(|r| r.rip) != Ok(e)) {
+                break;
+            }
+
+            // show-instructionが有効な場合は停止時に命令とともに表示済み
+            let rip = ptrace::getregs(dbg.info.pid)?.rip;
+            if !dbg.info.show_insn {
+                println!("RIP = {rip:#x}");
+            }
+            if i + 1 == count {
+                break;
+            }
+
+            let info = ptrace::getsiginfo(dbg.info.pid)?;
+            if info.si_signo != libc::SIGTRAP {
+                break;
+            }
+            if let Some(b) = dbg
+                .user_breaks()
+                .find(|b| b.enabled && b.addr as u64 == rip)
+            {
+                println!(
+                    "<<ブレークポイント {} に到達しました：Addr = {:p}>>",
+                    b.id, b.addr
+                );
+                break;
+            }
+        }
+
+        if count > 1 {
+            let rip = ptrace::getregs(dbg.info.pid)?.rip;
+            println!("<<{done}/{count}ステップ実行しました：RIP = {rip:#x}>>");
+        }
+        Ok(State::Running(dbg))
+    }
+
+    /// RIPが動的リンカ内の場合はエントリポイント (AT_ENTRY) のアドレス
+    fn ld_skip_target(&self) -> Option<u64> {
+        let rip = ptrace::getregs(self.info.pid).ok()?.rip;
+        let maps = read_maps(self.info.pid).ok()?;
+        let ld = interp_range(self.info.pid, &maps)?;
+        if rip < ld.start || ld.end <= rip {
+            return None;
+        }
+        auxv_value(self.info.pid, libc::AT_ENTRY)
+    }
+
+    /// 動的リンカ内からエントリポイントentryに一時ブレークポイントを設定して実行を再開
+    fn run_to_entry(mut self, entry: u64) -> Result<State, Box<dyn Error>> {
+        if let Err(e) = self.add_internal_break(entry, None) {
+            eprintln!("<<エントリポイントにブレークポイントを設定できません：{entry:#x}：{e}>>");
+            return self.step_once();
+        }
+        println!("<<動的リンカをスキップし、エントリポイント {entry:#x} まで実行します>>");
+
+        match self.do_continue()? {
+            State::Running(mut r) => {
+                if ptrace::getregs(r.info.pid)?.rip != entry {
+                    r.remove_internal_break(entry)?;
+                }
+                Ok(State::Running(r))
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// nextiを実行。RIPの命令がcallの場合は、次の命令に一時ブレークポイントを設定して実行を再開
+    /// 再帰呼び出しにより深い呼び出しで次の命令に到達した場合に止まらないよう、
+    /// RSPがcallの実行前以上となった場合のみ停止する
+    fn do_nexti(mut self) -> Result<State, Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let (code, _) = self.read_mem_partial(regs.rip as usize, 15);
+        let insn = match disas::decode(&code, regs.rip, 1, Syntax::Intel).map(|mut v| v.pop()) {
+            Ok(Some(insn)) => insn,
+            _ => return self.step_once(),
+        };
+        // bnd callのようにプレフィックスが付く場合がある
+        if insn.mnemonic.rsplit(' ').next() != Some("call") {
+            return self.step_once();
+        }
+
+        let next = regs.rip + insn.bytes.len() as u64;
+        let cond = BrkCond {
+            reg: "rsp".to_string(),
+            op: CmpOp::Ge,
+            value: regs.rsp,
+        };
+        if let Err(e) = self.add_internal_break(next, Some(cond)) {
+            eprintln!("<<{next:#x}にブレークポイントを設定できません：{e}>>");
+            return Ok(State::Running(self));
+        }
+
+        match self.do_continue()? {
+            State::Running(mut r) => {
+                // 呼び出し先のブレークポイントなどで先に停止した場合は、一時ブレークポイントを削除
+                if ptrace::getregs(r.info.pid)?.rip != next {
+                    r.remove_internal_break(next)?;
+                }
+                Ok(State::Running(r))
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// 1ステップ実行
+    fn step_once(self) -> Result<State, Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        if self.enabled_break(regs.rip as usize).is_some() {
+            // 次の実行アドレスがブレークポイントの場合は、
+            // 元の値に戻してステップ実行し、再度ブレークポイントを設定
+            match self.step_and_break()? {
+                State::Running(mut r) => {
+                    r.report_stop(Signal::SIGTRAP);
+                    Ok(State::Running(r))
+                }
+                n => Ok(n),
+            }
+        } else {
+            ptrace::step(self.info.pid, None)?;
+            self.wait_child()
+        }
+    }
+
+    /// ブレークポイントで停止していた場合は
+    /// 1ステップ実行しブレークポイントを再設定
+    fn step_and_break(mut self) -> Result<State, Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let addr = regs.rip as *mut c_void;
+        if self.enabled_break(addr as usize).is_none() {
+            return Ok(State::Running(self));
+        }
+
+        // wait_childで元に戻っていない場合もあるため、ここでも元の値に戻す
+        self.unset_break_at(addr)?;
+        ptrace::step(self.info.pid, None)?;
+        let status = waitpid(self.info.pid, None)?;
+        if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = status {
+            self.event("exit", exit_detail(status));
+            println!("<<子プロセスが終了しました>>");
+            self.info.pending_calls.clear();
+            let not_run = ZDbg::<NotRunning> {
+                info: self.info,
+                _state: NotRunning,
+            };
+            return Ok(State::NotRunning(not_run));
+        }
+
+        self.set_break_at(addr)?; // 再度ブレークポイントを設定
+        Ok(State::Running(self))
+    }
+
+    /// シグナルsigを渡して実行を再開
+    /// straceが有効な場合は次のシステムコールの入口か出口でも停止する
+    fn resume(&self, sig: Option<Signal>) -> nix::Result<()> {
+        if self.info.syscall_trace {
+            ptrace::syscall(self.info.pid, sig)
+        } else {
+            ptrace::cont(self.info.pid, sig)
+        }
+    }
+
+    /// システムコールの入口では番号を、出口では戻り値をイベントとして記録し、設定された動作を返す
+    /// ignoreでなければ表示する
+    fn syscall_event(&mut self) -> Result<Action, Box<dyn Error>> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let line = if self.info.in_syscall {
+            // -4095〜-1はエラー番号。大きな値はアドレスとみなして16進数で表示
+            match regs.rax as i64 {
+                ret @ -4095..=-1 => format!("  = -1 {}", Errno::from_i32(-ret as i32)),
+                ret @ 0..=0xffff => format!("  = {ret}"),
+                _ => format!("  = {:#x}", regs.rax),
+            }
+        } else {
+            let nr = regs.orig_rax;
+            match syscall::name(nr) {
+                Some(name) => format!("syscall {nr} ({name})"),
+                None => format!("syscall {nr}"),
+            }
+        };
+        self.info.in_syscall = !self.info.in_syscall;
+
+        let action = self.event("syscall", line.trim_start().to_string());
+        if action != Action::Ignore {
+            println!("{line}");
+        }
+        Ok(action)
+    }
+
+    /// forkとvforkのイベントで生成されたプロセスのPID
+    fn event_child(&self) -> String {
+        match ptrace::getevent(self.info.pid) {
+            Ok(pid) => format!("PID = {pid}"),
+            Err(e) => format!("PIDを取得できません：{e}"),
+        }
+    }
+
+    /// continueを実行
+    fn do_continue(self) -> Result<State, Box<dyn Error>> {
+        self.continue_with(None)
+    }
+
+    /// シグナルsigを渡して実行を再開
+    fn continue_with(self, sig: Option<Signal>) -> Result<State, Box<dyn Error>> {
+        // ブレークポイントで停止していた場合は1ステップ実行後再設定
+        match self.step_and_break()? {
+            State::Running(r) => {
+                // 実行再開
+                r.resume(sig)?;
+                r.wait_child()
+            }
+            n => Ok(n),
+        }
+    }
+
+    /// signal SIGUSR1を実行。シグナルを子プロセスに渡して実行を再開
+    /// シグナルは名前 (SIGを省略可) か番号で指定
+    fn do_signal(self, cmd: &[&str]) -> Result<State, Box<dyn Error>> {
+        let Some(arg) = cmd.get(1) else {
+            eprintln!("<<シグナルを指定してください\n例：signal SIGUSR1, signal 10>>");
+            return Ok(State::Running(self));
+        };
+        match parse_signal(arg) {
+            Some(sig) => self.continue_with(Some(sig)),
+            None => {
+                eprintln!("<<不明なシグナルです：{arg}>>");
+                Ok(State::Running(self))
+            }
+        }
+    }
+
+    /// 致命的なシグナルで停止した際に、シグナル、フォルトアドレス、
+    /// レジスタ、該当するマッピング、バックトレース、RIPからの逆アセンブルを表示
+    fn print_crash_report(&mut self, sig: Signal) {
+        if !self.info.crash_report || !is_fatal_signal(sig) {
+            return;
+        }
+
+        println!("<<クラッシュレポート：{sig}>>");
+
+        // SIGABRTはフォルトアドレスを持たない
+        if sig != Signal::SIGABRT {
+            if let Ok(info) = ptrace::getsiginfo(self.info.pid) {
+                let fault_addr = unsafe { info.si_addr() } as u64;
+                print!("フォルトアドレス：{fault_addr:#x}");
+                match find_map(self.info.pid, fault_addr) {
+                    Some(map) => println!(" ({map})"),
+                    None => println!(" (マッピングなし)"),
+                }
+            }
+        }
+
+        if let Ok(regs) = ptrace::getregs(self.info.pid) {
+            print_regs(&regs, self.info.prev_regs.as_ref(), self.width());
+            if let Some(map) = find_map(self.info.pid, regs.rip) {
+                println!("RIPのマッピング：{map}");
+            }
+        }
+
+        println!("バックトレース：");
+        if let Err(e) = self.backtrace(CRASH_FRAMES) {
+            eprintln!("<<バックトレースを取得できません：{e}>>");
+        }
+        println!("逆アセンブル：");
+        if let Err(e) = self.do_disas(&["disas"]) {
+            eprintln!("<<逆アセンブルできません：{e}>>");
+        }
+    }
+
+    /// 停止時の表示と、停止時に行う監視を実行
+    fn report_stop(&mut self, sig: Signal) {
+        // 変化したレジスタを表示するため、前回と今回の停止時のレジスタを保存
+        self.info.prev_regs = self.info.stop_regs.take();
+        self.info.stop_regs = ptrace::getregs(self.info.pid).ok();
+        self.print_stop_banner(&format!("<<子プロセスが停止しました：{sig}>>"));
+        if self.info.show_insn {
+            self.print_stop_insn();
+        }
+        if sig == Signal::SIGSTOP {
+            if let Some(line) = self.info.output_watch.take_hit() {
+                println!("<<出力がstop-on-outputのパターンに一致しました：{line}>>");
+            }
+        }
+        self.check_rss();
+        self.check_proc_watches();
+        self.print_crash_report(sig);
+    }
+
+    /// --log-callを設定したブレークポイントidへの呼び出しを記録し、戻りアドレスに一時ブレークポイントを設定
+    /// 関数の先頭で停止しているため、スタックの先頭が戻りアドレス
+    fn record_call(&mut self, id: usize, regs: &user_regs_struct) {
+        let Some(b) = self.user_breaks().find(|b| b.id == id) else {
+            return;
+        };
+        let name = b.sym.clone().unwrap_or_else(|| format!("{:p}", b.addr));
+        let ret_addr = match ptrace::read(self.info.pid, regs.rsp as *mut c_void) {
+            Ok(ret) => ret as u64,
+            Err(e) => {
+                eprintln!("<<{name}の戻りアドレスを読み込めません：{e}>>");
+                return;
+            }
+        };
+
+        let time = format_elapsed(self.info.run_start.elapsed());
+        let args = [regs.rdi, regs.rsi, regs.rdx, regs.rcx, regs.r8, regs.r9];
+        let depth = self
+            .info
+            .pending_calls
+            .iter()
+            .filter(|p| p.name == name)
+            .count();
+        let log = self
+            .info
+            .call_logs
+            .entry(name.clone())
+            .or_insert_with(CallLog::new);
+        let Some(idx) = log.push(time, args, depth) else {
+            return;
+        };
+
+        if let Err(e) = self.add_internal_break(ret_addr, None) {
+            eprintln!("<<戻りアドレス {ret_addr:#x} にブレークポイントを設定できません：{e}>>");
+            return;
+        }
+        self.info.pending_calls.push(PendingCall {
+            name,
+            idx,
+            ret_addr,
+            rsp: regs.rsp + 8,
+            start: Instant::now(),
+        });
+    }
+
+    /// --log-callで記録した呼び出しから戻った場合に、戻り値と所要時間を記録
+    /// 再帰呼び出しのより深い呼び出しから戻った場合はRSPが一致しないため何もしない
+    fn record_return(&mut self, ret_addr: u64, regs: &user_regs_struct) {
+        let pending = &mut self.info.pending_calls;
+        if let Some(i) = pending
+            .iter()
+            .rposition(|p| p.ret_addr == ret_addr && p.rsp == regs.rsp)
+        {
+            let p = pending.remove(i);
+            if let Some(log) = self.info.call_logs.get_mut(&p.name) {
+                log.set_return(p.idx, regs.rax, p.start.elapsed());
+            }
+        }
+
+        // この戻りアドレスを待つ呼び出しがなくなった場合は一時ブレークポイントを削除
+        // wait_childで元の値に戻しているため、ブレークポイントの情報のみ削除
+        if !self
+            .info
+            .pending_calls
+            .iter()
+            .any(|p| p.ret_addr == ret_addr)
+        {
+            self.info
+                .breakpoints
+                .retain(|b| !(b.internal && b.addr as u64 == ret_addr));
+        }
+    }
+
+    /// ブレークポイントidのヒットをログに記録
+    fn write_hit_log(&mut self, id: usize, regs: &user_regs_struct) {
+        let Some(b) = self.user_breaks().find(|b| b.id == id) else {
+            return;
+        };
+        let Some(log) = &b.log else {
+            return;
+        };
+
+        let mut line = format!(
+            "+{} hit={} rip={:#x}",
+            format_elapsed(self.info.run_start.elapsed()),
+            b.hit_count,
+            regs.rip
+        );
+        for e in log.exprs() {
+            match self.eval_expr(e, Some(regs)) {
+                Ok(val) => line.push_str(&format!(" {e}={val:#x}")),
+                Err(err) => line.push_str(&format!(" {e}=<{err}>")),
+            }
+        }
+
+        let max_size = self.info.log_max_size;
+        if let Some(log) = self.find_user_break(id).and_then(|b| b.log.as_mut()) {
+            if let Err(e) = log.write(&line, max_size) {
+                eprintln!("<<ログに書き込めません：{e}>>");
+            }
+        }
+    }
+
+    /// int3の実行による停止か
+    /// ステップ実行による停止やkillによるSIGTRAPはsi_codeで区別する
+    fn is_int3_trap(&self, sig: Signal) -> Result<bool, Box<dyn Error>> {
+        if sig != Signal::SIGTRAP {
+            return Ok(false);
+        }
+        let info = ptrace::getsiginfo(self.info.pid)?;
+        Ok(info.si_code == libc::SI_KERNEL)
+    }
+
+    /// follow-exec-nameが設定されている場合は、fork、vfork、execを通知するよう設定
+    /// straceのため、システムコールによる停止をSIGTRAPと区別できるよう常に設定
+    fn set_trace_options(&self) {
+        let mut opts = ptrace::Options::PTRACE_O_TRACESYSGOOD;
+        if self.info.follow_exec.is_some() {
+            opts |= ptrace::Options::PTRACE_O_TRACEFORK
+                | ptrace::Options::PTRACE_O_TRACEVFORK
+                | ptrace::Options::PTRACE_O_TRACEEXEC;
+        }
+        if let Err(e) = ptrace::setoptions(self.info.pid, opts) {
+            eprintln!("<<ptraceのオプションを設定できません：{e}>>");
+        }
+    }
+
+    /// follow-exec-nameのために自動的にトレースされた子孫のプロセスのイベントを処理
+    /// execした実行ファイルがパターンに一致した場合はそのPIDを返す
+    /// 一致しない場合はexecした時点でdetachし、execするまではシグナルを転送して実行を継続させる
+    /// 親から引き継いだ0xccで停止した場合は、元の値に戻して1ステップ実行してから継続させる
+    fn handle_descendant(&mut self, status: WaitStatus) -> Option<Pid> {
+        if let Some(pid) = status.pid() {
+            self.add_descendant(pid, false);
+        }
+        match status {
+            WaitStatus::PtraceEvent(pid, _, e) if e == ptrace::Event::PTRACE_EVENT_EXEC as i32 => {
+                let exe = exe_path(pid);
+                let pat = self.info.follow_exec.as_deref().unwrap_or("");
+                self.info.descendants.retain(|d| d.pid != pid);
+                if glob_match(pat, &exe) {
+                    return Some(pid);
+                }
+                let _ = ptrace::detach(pid, None);
+            }
+            WaitStatus::PtraceEvent(pid, _, e) => {
+                self.add_forked(pid, e);
+                let _ = ptrace::cont(pid, None);
+            }
+            // 自動的にトレースされた直後のSIGSTOPは転送しない
+            WaitStatus::Stopped(pid, Signal::SIGSTOP) => {
+                let _ = ptrace::cont(pid, None);
+            }
+            WaitStatus::Stopped(pid, sig) => {
+                let sig = self.step_over_inherited(pid, sig);
+                let _ = ptrace::cont(pid, sig.filter(|s| *s != Signal::SIGSTOP));
+            }
+            WaitStatus::Exited(pid, _) | WaitStatus::Signaled(pid, ..) => {
+                self.info.descendants.retain(|d| d.pid != pid);
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// 子孫のプロセスpidを記録。vforkの場合は既に記録済みでも更新する
+    fn add_descendant(&mut self, pid: Pid, vfork: bool) {
+        match self.info.descendants.iter_mut().find(|d| d.pid == pid) {
+            Some(d) => d.vfork |= vfork,
+            None => self.info.descendants.push(Descendant { pid, vfork }),
+        }
+    }
+
+    /// pidのforkかvforkのイベントeで生成された子プロセスを記録
+    fn add_forked(&mut self, pid: Pid, e: i32) {
+        let vfork = e == ptrace::Event::PTRACE_EVENT_VFORK as i32;
+        if !vfork && e != ptrace::Event::PTRACE_EVENT_FORK as i32 {
+            return;
+        }
+        if let Ok(child) = ptrace::getevent(pid) {
+            self.add_descendant(Pid::from_raw(child as i32), vfork);
+        }
+    }
+
+    /// シグナルsigで停止した子孫のプロセスpidを再開する際に渡すシグナル
+    /// 親から引き継いだブレークポイントの0xccで停止した場合は、RIPを戻し、
+    /// 元の値で1ステップ実行してから0xccを書き戻す。その間に受信したシグナルがあればそれを返す
+    /// vforkの子プロセスは親とメモリを共有しているが、その間親は停止しているため書き換えてよい
+    fn step_over_inherited(&self, pid: Pid, sig: Signal) -> Option<Signal> {
+        if sig != Signal::SIGTRAP {
+            return Some(sig);
+        }
+        let Ok(mut regs) = ptrace::getregs(pid) else {
+            return Some(sig);
+        };
+        let addr = regs.rip.wrapping_sub(1) as *mut c_void;
+        let Some(b) = self
+            .info
+            .breakpoints
+            .iter()
+            .find(|b| b.enabled && b.addr == addr)
+        else {
+            return Some(sig);
+        };
+        if !matches!(ptrace::getsiginfo(pid), Ok(info) if info.si_code == libc::SI_KERNEL) {
+            return Some(sig);
+        }
+        let Ok(val) = ptrace::read(pid, addr) else {
+            return Some(sig);
+        };
+
+        regs.rip -= 1;
+        let orig = (val & !0xff) | (b.orig_byte & 0xff);
+        if ptrace::setregs(pid, regs).is_err()
+            || unsafe { ptrace::write(pid, addr, orig as *mut c_void) }.is_err()
+            || ptrace::step(pid, None).is_err()
+        {
+            return None;
+        }
+        // ステップ実行の前にシグナルを受信した場合は、再開後に再び0xccで停止する
+        let pending = match waitpid(pid, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::Stopped(_, Signal::SIGTRAP)) => None,
+            Ok(WaitStatus::Stopped(_, sig)) => Some(sig),
+            _ => return None,
+        };
+        let _ = unsafe { ptrace::write(pid, addr, val as *mut c_void) };
+        pending
+    }
+
+    /// 自動的にトレースしている子孫のプロセスをすべてdetach
+    /// 停止させてから、forkの子プロセスは引き継いだ0xccを元の値に戻してdetachする
+    /// vforkの子プロセスは親とメモリを共有しているため戻さない
+    fn detach_descendants(&mut self) {
+        while let Some(d) = self.info.descendants.pop() {
+            if kill(d.pid, Signal::SIGSTOP).is_err() {
+                continue;
+            }
+            let mut restore = !d.vfork;
+            loop {
+                match waitpid(d.pid, Some(WaitPidFlag::__WALL)) {
+                    Ok(WaitStatus::Stopped(pid, sig)) => {
+                        let sig = self.step_over_inherited(pid, sig);
+                        if sig != Some(Signal::SIGSTOP) {
+                            let _ = ptrace::cont(pid, sig);
+                            continue;
+                        }
+                        if restore {
+                            self.restore_inherited(pid);
+                        }
+                        let _ = ptrace::detach(pid, None);
+                        break;
+                    }
+                    // execした場合はメモリが置き換わっているため、元の値に戻す必要はない
+                    Ok(WaitStatus::PtraceEvent(pid, _, e)) => {
+                        if e == ptrace::Event::PTRACE_EVENT_EXEC as i32 {
+                            restore = false;
+                        }
+                        self.add_forked(pid, e);
+                        let _ = ptrace::cont(pid, None);
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// 停止している子孫のプロセスpidのメモリにある、親から引き継いだ0xccを元の値に戻す
+    fn restore_inherited(&self, pid: Pid) {
+        for b in self.info.breakpoints.iter().filter(|b| b.enabled) {
+            if let Ok(val) = ptrace::read(pid, b.addr) {
+                let orig = (val & !0xff) | (b.orig_byte & 0xff);
+                let _ = unsafe { ptrace::write(pid, b.addr, orig as *mut c_void) };
+            }
+        }
+    }
+
+    /// execしたプロセスpidにトレース対象を切り替えて停止
+    /// 元のプロセスはブレークポイントを元に戻してdetachし、実行を継続させる
+    /// シンボルで設定したブレークポイントは新しい実行ファイルで解決し直し、それ以外は無効化する
+    fn follow_exec(mut self, pid: Pid) -> Result<ZDbg<Running>, Box<dyn Error>> {
+        let old = self.info.pid;
+        if kill(old, Signal::SIGSTOP).is_ok() {
+            loop {
+                match waitpid(old, Some(WaitPidFlag::__WALL))? {
+                    WaitStatus::Stopped(_, Signal::SIGSTOP) => {
+                        for b in self.info.breakpoints.iter().filter(|b| b.enabled) {
+                            let _ = self.unset_break_at(b.addr);
+                        }
+                        let _ = ptrace::detach(old, None);
+                        break;
+                    }
+                    WaitStatus::Stopped(_, sig) => ptrace::cont(old, sig)?,
+                    WaitStatus::PtraceEvent(..) => ptrace::cont(old, None)?,
+                    _ => break,
+                }
+            }
+        }
+
+        let exe = exe_path(pid);
+        println!(
+            "<<PID {pid}が{exe}をexecしたため、トレース対象を切り替えました (PID {old}はdetach)>>"
+        );
+        self.info.pid = pid;
+        self.info.filename = exe;
+        self.info.symbols = Symbols::load(&self.info.filename);
+        self.info.patches.clear();
+        self.info.breakpoints.retain(|b| !b.internal);
+        self.info.dl_brk = None;
+
+        let symbols = self.info.symbols.as_ref().ok();
+        for b in self.info.breakpoints.iter_mut().filter(|b| b.enabled) {
+            let name = b.sym.as_deref().and_then(|s| s.split(" -> ").next());
+            match name.and_then(|name| symbols?.lookup(name)) {
+                Some(addr) => b.addr = addr as *mut c_void,
+                None => {
+                    b.enabled = false;
+                    println!(
+                        "<<ブレークポイント {} は新しい実行ファイルで解決できないため無効にしました>>",
+                        b.id
+                    );
+                }
+            }
+        }
+
+        self.relocate_breaks();
+        self.set_break()?;
+        self.watch_libraries();
+        self.set_trace_options();
+        self.report_stop(Signal::SIGTRAP);
+        Ok(self)
+    }
+
+    /// 子プロセスをwait。子プロセスが終了した場合はNotRunning状態に遷移
+    fn wait_child(mut self) -> Result<State, Box<dyn Error>> {
+        loop {
+            // follow-exec-nameが設定されている場合は、子孫のプロセスのイベントも受け取る
+            let status = if self.info.follow_exec.is_some() {
+                waitpid(None, Some(WaitPidFlag::__WALL))?
+            } else {
+                waitpid(self.info.pid, None)?
+            };
+            if status.pid().is_some_and(|pid| pid != self.info.pid) {
+                if let Some(pid) = self.handle_descendant(status) {
+                    return Ok(State::Running(self.follow_exec(pid)?));
+                }
+                continue;
+            }
+
+            match status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.event("exit", exit_detail(status));
+                    println!("<<子プロセスが終了しました>>");
+                    self.detach_descendants();
+                    self.info.breakpoints.retain(|b| !b.internal);
+                    self.info.pending_calls.clear();
+                    self.info.in_syscall = false;
+                    for log in self
+                        .info
+                        .breakpoints
+                        .iter_mut()
+                        .filter_map(|b| b.log.as_mut())
+                    {
+                        log.close();
+                    }
+                    let not_run = ZDbg::<NotRunning> {
+                        info: self.info,
+                        _state: NotRunning,
+                    };
+                    return Ok(State::NotRunning(not_run));
+                }
+                WaitStatus::Stopped(_, sig) => {
+                    // int3による停止でなければブレークポイントの処理は不要
+                    if !self.is_int3_trap(sig)? {
+                        let rip = ptrace::getregs(self.info.pid)?.rip;
+                        let action = self.event(sig.as_str(), format!("rip={rip:#x}"));
+
+                        // catch signalで指定したシグナルはset eventの設定によらず停止
+                        // 停止後に再開する際、シグナルは子プロセスに渡さない
+                        if self.info.catch_signals.contains(&sig) {
+                            self.report_stop(sig);
+                            println!("<<シグナル {sig} をキャッチしました>>");
+                            return Ok(State::Running(self));
+                        }
+
+                        match action {
+                            Action::Stop => {
+                                self.report_stop(sig);
+                                return Ok(State::Running(self));
+                            }
+                            Action::Log => println!("<<{sig}を受信しました。実行を継続します>>"),
+                            Action::Ignore => (),
+                        }
+                        // SIGSTOPを渡すと再び停止するため、それ以外のシグナルのみ子プロセスに渡す
+                        self.resume((sig != Signal::SIGSTOP).then_some(sig))?;
+                        continue;
+                    }
+
+                    // 停止したアドレスがブレークポイントの場合は、
+                    // プログラムカウンタを1減らし、0xccに書き換えたメモリを元の値に戻す
+                    let mut regs = ptrace::getregs(self.info.pid)?;
+                    let addr = regs.rip.wrapping_sub(1) as *mut c_void;
+                    // 内部用とユーザのブレークポイントが重なっている場合はユーザのものを優先
+                    let brk = self
+                        .info
+                        .breakpoints
+                        .iter_mut()
+                        .filter(|b| b.enabled && b.addr == addr)
+                        .min_by_key(|b| b.internal);
+                    let (id, temporary, internal, mut stop, logging) = match brk {
+                        Some(b) => {
+                            let cond_ok = b.cond.as_ref().is_none_or(|c| {
+                                c.eval(&user_regs_struct {
+                                    rip: addr as u64,
+                                    ..regs
+                                })
+                            });
+
+                            // 条件が成立した場合のみヒットとして数え、無視する回数が残っていれば減らす
+                            let mut stop = cond_ok;
+                            if cond_ok {
+                                b.hit_count += 1;
+                                if b.ignore_count > 0 {
+                                    b.ignore_count -= 1;
+                                    stop = false;
+
+                                    // --nthの場合は100回ごとに進捗を表示
+                                    if let Some((base, n)) = b.nth {
+                                        let done = (b.hit_count - base) as u64;
+                                        if done.is_multiple_of(100) {
+                                            println!("<<ブレークポイント {}：{done}/{n}回>>", b.id);
+                                        }
+                                    }
+                                }
+                            }
+                            (b.id, b.temporary, b.internal, stop, b.log.is_some())
+                        }
+                        // プログラム自身のint3
+                        None => {
+                            self.event(sig.as_str(), format!("int3 rip={addr:p}"));
+                            self.report_stop(sig);
+                            return Ok(State::Running(self));
+                        }
+                    };
+
+                    regs.rip -= 1;
+                    ptrace::setregs(self.info.pid, regs)?;
+                    self.unset_break_at(addr)?;
+
+                    // 共有ライブラリの読み込みと解放
+                    // 停止しない場合は、同じアドレスのユーザのブレークポイントとして処理を続ける
+                    if self.info.dl_brk == Some(addr as u64) {
+                        let loaded = self.library_event();
+                        if !loaded.is_empty() {
+                            self.report_stop(sig);
+                            for lib in loaded {
+                                println!("<<共有ライブラリを読み込みました：{lib}>>");
+                            }
+                            return Ok(State::Running(self));
+                        }
+                        if internal {
+                            self = match self.step_and_break()? {
+                                State::Running(r) => r,
+                                n => return Ok(n),
+                            };
+                            self.resume(None)?;
+                            continue;
+                        }
+                    }
+
+                    // --log-callの呼び出しと戻りは記録のみ行い、停止しない
+                    let ret_addr = addr as u64;
+                    if internal
+                        && self
+                            .info
+                            .pending_calls
+                            .iter()
+                            .any(|p| p.ret_addr == ret_addr)
+                    {
+                        self.record_return(ret_addr, &regs);
+                        stop = false;
+                    } else if stop && self.find_user_break(id).is_some_and(|b| b.log_call) {
+                        self.record_call(id, &regs);
+                        stop = false;
+                    }
+
+                    // ログを設定したブレークポイントは記録のみ行い、停止しない
+                    if stop && logging {
+                        stop = false;
+                        self.write_hit_log(id, &regs);
+                        if temporary {
+                            self.remove_break(id);
+                        }
+                    }
+
+                    if stop {
+                        let (class, detail) = if internal {
+                            ("internal", format!("rip={addr:p}"))
+                        } else {
+                            ("breakpoint", format!("id={id} rip={addr:p}"))
+                        };
+                        match self.event(class, detail) {
+                            Action::Stop => (),
+                            Action::Log => {
+                                stop = false;
+                                println!(
+                                    "<<ブレークポイント {id} に到達しました。実行を継続します>>"
+                                );
+                            }
+                            Action::Ignore => stop = false,
+                        }
+                    }
+
+                    // 条件が成立していないか、無視するヒットの場合は停止せずに実行を再開
+                    if !stop {
+                        self = match self.step_and_break()? {
+                            State::Running(r) => r,
+                            n => return Ok(n),
+                        };
+                        self.resume(None)?;
+                        continue;
+                    }
+
+                    self.report_stop(sig);
+                    if let Some(b) = self.find_user_break(id) {
+                        if let Some((_, n)) = b.nth.take() {
+                            println!(
+                                "<<ブレークポイント {id} の{n}回目のヒットで停止しました：hits = {}>>",
+                                b.hit_count
+                            );
+                        }
+                    }
+
+                    // 一時ブレークポイントは再設定されないよう削除
+                    // ユーザのブレークポイントと重なっていた内部用の一時ブレークポイントも削除
+                    self.info
+                        .breakpoints
+                        .retain(|b| !(b.internal && b.temporary && b.addr == addr));
+                    if !internal && temporary {
+                        self.remove_break(id);
+                        println!(
+                            "<<一時ブレークポイント {id} を削除しました：Addr = {:p}>>",
+                            addr
+                        );
+                    }
+
+                    return Ok(State::Running(self));
+                }
+                // 子プロセス自身のexec、fork、vfork
+                // forkとvforkで生成されたプロセスは子孫のプロセスとして扱う
+                WaitStatus::PtraceEvent(pid, _, e) => {
+                    self.add_forked(pid, e);
+                    let (class, detail) = if e == ptrace::Event::PTRACE_EVENT_EXEC as i32 {
+                        // 動的リンカも置き換わるため、_dl_debug_stateを検索し直す
+                        self.info.breakpoints.retain(|b| !b.internal);
+                        self.info.dl_brk = None;
+                        self.watch_libraries();
+                        ("exec", exe_path(self.info.pid))
+                    } else if e == ptrace::Event::PTRACE_EVENT_FORK as i32 {
+                        ("fork", self.event_child())
+                    } else if e == ptrace::Event::PTRACE_EVENT_VFORK as i32 {
+                        ("vfork", self.event_child())
+                    } else {
+                        self.resume(None)?;
+                        continue;
+                    };
+                    match self.event(class, detail.clone()) {
+                        Action::Stop => {
+                            self.report_stop(Signal::SIGTRAP);
+                            return Ok(State::Running(self));
+                        }
+                        Action::Log => println!("<<{class}しました：{detail}>>"),
+                        Action::Ignore => (),
+                    }
+                    self.resume(None)?;
+                }
+                // straceによるシステムコールの入口と出口での停止
+                WaitStatus::PtraceSyscall(_) => {
+                    if self.syscall_event()? == Action::Stop {
+                        self.report_stop(Signal::SIGTRAP);
+                        return Ok(State::Running(self));
+                    }
+                    self.resume(None)?;
+                }
+                _ => return Err("waitpidの返り値が不正です".into()),
+            }
+        }
+    }
+}
+
+/// SIGUSR1、USR1、10のいずれかの形式でシグナルを指定
+fn parse_signal(s: &str) -> Option<Signal> {
+    if let Ok(n) = s.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    let name = s.to_ascii_uppercase();
+    if name.starts_with("SIG") {
+        name.parse().ok()
+    } else {
+        format!("SIG{name}").parse().ok()
+    }
+}
+
+/// events recentで表示する終了の詳細
+fn exit_detail(status: WaitStatus) -> String {
+    match status {
+        WaitStatus::Exited(_, code) => format!("status={code}"),
+        WaitStatus::Signaled(_, sig, _) => sig.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// *と?を使ったパターンにsが一致するか
+/// パターンに/が含まれない場合は、sのファイル名部分と照合する
+fn glob_match(pat: &str, s: &str) -> bool {
+    fn matches(p: &[u8], s: &[u8]) -> bool {
+        match (p.first(), s.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => matches(&p[1..], s) || (!s.is_empty() && matches(p, &s[1..])),
+            (Some(b'?'), Some(_)) => matches(&p[1..], &s[1..]),
+            (Some(a), Some(b)) if a == b => matches(&p[1..], &s[1..]),
+            _ => false,
+        }
+    }
+
+    let s = if pat.contains('/') {
+        s
+    } else {
+        s.rsplit('/').next().unwrap_or(s)
+    };
+    matches(pat.as_bytes(), s.as_bytes())
+}
+
+/// 経過時間をHH:MM:SS.mmmの形式に変換
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// ヘルプを表示
+fn do_help() {
+    println!(
+        r#"コマンド一覧 (括弧内は省略記法)
+break 0x8000 : ブレークポイントを0x8000番地に設定 (b 0x8000)
+break main   : ブレークポイントを関数mainに設定
+break 0x8000 if rdi == 0x20 : rdiが0x20の場合のみ停止するブレークポイントを設定
+break 0x8000 log hits.log $rdi : 停止せずにヒットをhits.logに記録 (式の値も記録)
+break main --nth 500 : 500回目の呼び出しで停止 (100回ごとに進捗を表示)
+break main --nth-from-now 500 : 設定済みのブレークポイントで、今から500回目のヒットで停止
+break main --log-call : 停止せずにmainの引数、戻り値、所要時間を記録
+calls main   : --log-callで記録したmainの呼び出しを表示
+tbreak 0x8000 : 一度停止したら削除されるブレークポイントを設定
+info breakpoints : ブレークポイントを一覧表示 (info break、i b)
+patch 0x8000 nop 5        : 0x8000から5バイトをnopで埋める
+patch 0x8000 jmp 0x8100   : 0x8000に0x8100へのjmpを書き込む (書き換えた命令の残りはnopで埋める)
+patch 0x8000 bytes de ad  : 0x8000にバイト列を書き込む (書き換えた命令の残りはnopで埋める)
+patch undo 1 : 1番のパッチを元に戻す
+info patches : パッチを一覧表示
+info base    : PIEのベースアドレスを表示
+info maps [stack] : メモリマップを表示 (パーミッションかパスにstackを含むもののみ)
+info functions [pat] : 関数のシンボルを一覧表示 (patを含むもののみ)
+enable 1     : 1番のブレークポイントを有効化
+disable 1    : 1番のブレークポイントを無効化
+ignore 1 10  : 1番のブレークポイントの次の10回のヒットでは停止しない
+delete 1     : 1番のブレークポイントを削除 (d 1、引数なしですべて削除)
+catch signal SIGUSR1 : SIGUSR1を受信したら停止 (引数なしで一覧を表示)
+delete catch signal SIGUSR1 : SIGUSR1のキャッチを削除
+run a b      : 引数a bを渡してプログラムを実行 (r)
+attach 1234  : 実行中のPID 1234のプロセスにattach
+detach       : プロセスからdetachし、実行を継続させる
+continue     : プログラムを再開 (c)
+signal SIGUSR1 : SIGUSR1を子プロセスに渡して再開 (signal USR1、signal 10も可)
+finish       : 現在の関数から戻るまで実行し、戻り値を表示 (fin)
+until 0x8000 : 指定したアドレスまで実行 (u。ブレークポイントは残さない)
+stepi        : 機械語レベルで1ステップ実行 (s)
+stepi 5      : 機械語レベルで5ステップ実行し、各ステップのRIPを表示
+stepi --skip-ld 5 : 動的リンカ内ではステップ実行せずにエントリポイントまで実行
+nexti        : 機械語レベルで1ステップ実行。callの場合は呼び出し先から戻るまで実行 (n)
+registers    : レジスタを表示 (regs)
+registers diff : 前回の停止から変化したレジスタを表示 (registersでは*を付けて表示)
+registers float : x87とSSEのレジスタを表示
+registers set rax 0x10 : レジスタraxに0x10を設定 (setreg rax 0x10)
+delta begin [0x8000 64] : レジスタと0x8000からの64バイトを保存
+delta end    : delta beginからの変化を表示
+strace       : continueの実行中にシステムコールと戻り値を表示 (再度実行すると停止)
+info events  : イベント (シグナル、ブレークポイント、システムコール、fork、exec) ごとの動作を表示
+set event SIGUSR1 log : SIGUSR1を受信したら表示して実行を継続 (stop、log、ignore)
+set event library stop : 共有ライブラリが読み込まれたら停止 (初期値はignore)
+events recent 20 : 最近の20個のイベントを表示
+watch-rss 64 : 停止時にRSSが64MBを超えていたら報告 (offで解除)
+watch-proc fd-count > 1000 : 停止時に/procの値 (fd-count, threads, vmsize) を監視 (clearで解除)
+set show-instruction on|off : 停止時にRIPの命令を表示する
+set skip-dynamic-linker on|off : stepiで常に--skip-ldを指定する
+set follow-exec-name cc* : 子孫のプロセスがパターンに一致するファイルをexecした場合にトレース対象を切り替え (offで解除)
+set log-max-size 64 : ブレークポイントのログの最大サイズ (MB)
+set crash-report on|off : 致命的なシグナルでの停止時にレポートを表示
+calc 0x10+$rsp : 式を計算して表示し、結果を$1などに保存 (レジスタは実行中のみ使用可能)
+calc main + 0x10 : シンボルのアドレスを使用して計算 (実行中はPIEのベースアドレスを加算)
+print $rsp+0x10 : 式を計算して16進数で表示し、結果を$1などに保存 (p)
+set $base = 0x555555554000 : 式で使用できる変数を定義 ($_は直前の結果)
+info convenience : 変数の一覧を表示
+info zdbg    : デバッガのバージョン、利用可能な機能、制限を表示
+set width 100 : 出力幅を100文字に設定 (0で端末幅)
+stop-on-output "error" --count 2 : 標準エラー出力の2回目の"error"を含む行で停止 (次のrunから有効)
+stop-on-output clear : stop-on-outputのパターンをすべて削除
+repro-check 5 : ターゲットを5回実行し、出力と終了ステータスが同一か確認
+memory read 0x8000 32 : 0x8000から32バイトを8バイトずつ表示 (x/4x 0x8000。先頭のワードを$1などに保存)
+memory write 0x8000 0x1234 : 0x8000に64ビットの値0x1234を書き込む
+disassemble 0x8000 10 : 0x8000から10命令を逆アセンブル (disas、dis。省略時はRIPから10命令)
+set disassembly-flavor intel|att : 逆アセンブルの構文 (初期値はintel)
+backtrace    : フレームポインタをたどって呼び出し元を表示 (bt)
+medit 0x8000 128 : 0x8000からの128バイトを対話的に編集 (wで書き込み、qで中止)
+medit 0x8000 128 --force : 書き込み不可のマッピングでも確認せずに書き込む
+write 0x8000 de ad : 0x8000にバイト列de adを書き込む
+x 0x8000 64  : 0x8000から64バイトを16進数とASCIIで表示 (省略時64バイト。x rspのように式も指定可能。先頭の8バイトを$1などに保存)
+maps snapshot base : メモリマッピングをbaseという名前で保存
+maps diff base     : 保存したマッピングbaseと現在のマッピングを比較
+exit         : 終了
+set auto-run on|off : 実行中のみ使用可能なコマンドで自動的にmainまでrunする
+set stop-on-entry on|off : runした際にエントリポイントで停止する
+set bind-now on|off : 動的リンクを起動時にすべて解決する (ifuncのブレークポイント用)
+help         : このヘルプを表示 (h)"#
+    );
+}
+
+/// レジスタを表示。1行に並べるレジスタの数はwidthに合わせる
+/// prevが指定された場合は、prevから変化したレジスタの前に*を付ける
+fn print_regs(regs: &user_regs_struct, prev: Option<&user_regs_struct>, width: usize) {
+    let cells: Vec<String> = REG_NAMES
+        .iter()
+        .filter(|name| **name != "eflags")
+        .map(|name| {
+            let val = reg_value(regs, name).unwrap();
+            let mark = match prev {
+                Some(prev) if reg_value(prev, name) != Some(val) => "*",
+                Some(_) => " ",
+                None => "",
+            };
+            format!("{mark}{:>3}: {val:#016x}", name.to_uppercase())
+        })
+        .collect();
+    for line in layout::columns(&cells, width, ", ") {
+        println!("{line}");
+    }
+}
+
+/// oldから変化したレジスタを「old → new」の形式で表示。変化があった場合はtrue
+fn print_reg_diff(old: &user_regs_struct, new: &user_regs_struct) -> bool {
+    let mut changed = false;
+    for name in REG_NAMES {
+        let old = reg_value(old, name).unwrap();
+        let new = reg_value(new, name).unwrap();
+        if old != new {
+            println!("{name:>6}: {old:#x} → {new:#x}");
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// break main --nth 500の引数から--nthと--nth-from-nowを取り除き、
+/// (--nth-from-nowか, 停止するヒット数) を返す
+fn take_nth_opt(cmd: &mut Vec<&str>) -> Result<Option<(bool, u64)>, String> {
+    let Some(i) = cmd
+        .iter()
+        .position(|a| *a == "--nth" || *a == "--nth-from-now")
+    else {
+        return Ok(None);
+    };
+
+    let n = match cmd.get(i + 1).map(|n| n.parse::<u64>()) {
+        Some(Ok(n)) if n > 0 => n,
+        _ => return Err(format!("{}には1以上の回数を指定してください", cmd[i])),
+    };
+    let from_now = cmd[i] == "--nth-from-now";
+    cmd.drain(i..i + 2);
+    Ok(Some((from_now, n)))
+}
+
+/// 16進数で指定されたブレークポイントのアドレスを取得
+fn get_break_addr(addr_str: &str) -> Option<*mut c_void> {
+    let hex = match addr_str.strip_prefix("0x") {
+        Some(hex) => hex,
+        None => {
+            eprintln!("<<アドレスは16進数でのみ指定可能です\n例：break 0x8000>>");
+            return None;
+        }
+    };
+
+    let addr = match usize::from_str_radix(hex, 16) {
+        Ok(addr) => addr,
+        Err(e) => {
+            eprintln!("<<アドレス変換エラー：{}>>", e);
+            return None;
+        }
+    } as *mut c_void;
+
+    Some(addr)
+}
+
+/// レジスタ名からレジスタの値を取得
+fn reg_value(regs: &user_regs_struct, name: &str) -> Option<u64> {
+    let mut regs = *regs;
+    reg_mut(&mut regs, name).map(|r| *r)
+}
+
+/// 式や条件、setregで指定可能なレジスタ名
+const REG_NAMES: &[&str] = &[
+    "rip", "rsp", "rbp", "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12",
+    "r13", "r14", "r15", "eflags",
+];
+
+/// レジスタ名からuser_regs_structのフィールドを取得
+fn reg_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    let reg = match name {
+        "rip" => &mut regs.rip,
+        "rsp" => &mut regs.rsp,
+        "rbp" => &mut regs.rbp,
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "eflags" => &mut regs.eflags,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+/// set $foo = 式の形式か
+fn is_var_assign(cmd: &[&str]) -> bool {
+    cmd.get(1).is_some_and(|c| c.starts_with('$'))
+}
+
+/// reg_valueで値を取得できるレジスタ名か
+fn is_reg_name(name: &str) -> bool {
+    REG_NAMES.contains(&name)
+}
+
+impl MapEntry {
+    /// /proc/<pid>/mapsの1行をパース
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let (start, end) = fields.next()?.split_once('-')?;
+        let perms = fields.next()?.to_string();
+        let offset = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let path = fields.nth(2).unwrap_or("").to_string();
+
+        Some(MapEntry {
+            start: u64::from_str_radix(start, 16).ok()?,
+            end: u64::from_str_radix(end, 16).ok()?,
+            perms,
+            offset,
+            path,
+        })
+    }
+}
+
+impl std::fmt::Display for MapEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:#x}-{:#x} {} {:#x} {}",
+            self.start, self.end, self.perms, self.offset, self.path
+        )
+    }
+}
+
+impl std::fmt::Display for ProcMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProcMetric::FdCount => write!(f, "fd-count"),
+            ProcMetric::Threads => write!(f, "threads"),
+            ProcMetric::VmSize => write!(f, "vmsize"),
+        }
+    }
+}
+
+impl std::fmt::Display for CmpOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Gt => ">",
+            CmpOp::Le => "<=",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl std::fmt::Display for BrkCond {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {:#x}", self.reg, self.op, self.value)
+    }
+}
+
+impl BrkCond {
+    /// ifに続く「レジスタ 比較演算子 値」をパース
+    fn parse(args: &[&str]) -> Result<Self, String> {
+        let [reg, op, value] = args else {
+            return Err("条件は「レジスタ 比較演算子 値」で指定してください".into());
+        };
+
+        let reg = reg.trim_start_matches('$').to_string();
+        if !is_reg_name(&reg) {
+            return Err(format!("不明なレジスタです：{reg}"));
+        }
+
+        let op = CmpOp::parse(op).ok_or(format!("比較演算子が不正です：{op}"))?;
+
+        let value = match value.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => value.parse::<u64>(),
+        }
+        .map_err(|e| format!("値変換エラー：{value}：{e}"))?;
+
+        Ok(BrkCond { reg, op, value })
+    }
+
+    /// 条件を評価
+    fn eval(&self, regs: &user_regs_struct) -> bool {
+        match reg_value(regs, &self.reg) {
+            Some(val) => self.op.eval(val, self.value),
+            None => true,
+        }
+    }
+}
+
+impl CmpOp {
+    /// 比較演算子の文字列から変換
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "==" => Some(CmpOp::Eq),
+            "!=" => Some(CmpOp::Ne),
+            "<" => Some(CmpOp::Lt),
+            ">" => Some(CmpOp::Gt),
+            "<=" => Some(CmpOp::Le),
+            ">=" => Some(CmpOp::Ge),
+            _ => None,
+        }
+    }
+
+    /// lhs op rhsを評価
+    fn eval(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            CmpOp::Eq => lhs == rhs,
+            CmpOp::Ne => lhs != rhs,
+            CmpOp::Lt => lhs < rhs,
+            CmpOp::Gt => lhs > rhs,
+            CmpOp::Le => lhs <= rhs,
+            CmpOp::Ge => lhs >= rhs,
+        }
+    }
+}
+
+/// 子プロセスがexecまでの間に失敗した処理
+#[derive(Debug, Clone, Copy)]
+enum ChildStage {
+    Redirect, // 標準エラー出力のdup2
+    Traceme,  // PTRACE_TRACEME
+    Exec,     // execvp
+}
+
+/// 子プロセス内で、失敗した処理とerrnoをパイプに書き込んで終了
+fn exit_with_errno(fd: RawFd, stage: ChildStage, e: Errno) -> ! {
+    let mut buf = [0; 8];
+    buf[..4].copy_from_slice(&(stage as i32).to_ne_bytes());
+    buf[4..].copy_from_slice(&(e as i32).to_ne_bytes());
+    let _ = write(fd, &buf);
+    unsafe { libc::_exit(127) }
+}
+
+/// 子プロセスがパイプに書き込んだ、失敗した処理とerrnoを読み込む
+fn read_errno(fd: RawFd) -> Option<(ChildStage, Errno)> {
+    let mut buf = [0; 8];
+    if read(fd, &mut buf) != Ok(8) {
+        return None;
+    }
+
+    let stage = match i32::from_ne_bytes(buf[..4].try_into().unwrap()) {
+        0 => ChildStage::Redirect,
+        1 => ChildStage::Traceme,
+        _ => ChildStage::Exec,
+    };
+    let errno = Errno::from_i32(i32::from_ne_bytes(buf[4..].try_into().unwrap()));
+    Some((stage, errno))
+}
+
+/// exec直後の子プロセスを調べ、この環境で制限される機能があれば警告して診断結果を表示
+fn warn_degraded(pid: Pid) {
+    let mut degraded = Vec::new();
+    match fs::read_to_string(format!("/proc/{pid}/personality")) {
+        Ok(p) => {
+            let p = u64::from_str_radix(p.trim(), 16).unwrap_or(0);
+            if p & Persona::ADDR_NO_RANDOMIZE.bits() as u64 == 0 {
+                eprintln!("<<ASLRを無効にできませんでした。実行ごとにアドレスが変わります>>");
+                degraded.push("ASLRの無効化 (実行ごとにアドレスが変わります)");
+            }
+        }
+        Err(_) => {
+            eprintln!("<</procが利用できません。一部の機能は使用できません>>");
+            degraded.push("maps、watch-rss、watch-proc、info base");
+            degraded.push("共有ライブラリの関数へのブレークポイント");
+        }
+    }
+
+    if !degraded.is_empty() {
+        selftest::print_diagnosis(&degraded);
+    }
+}
+
+/// bufをaddrから始まるメモリとして、16進数とASCIIで表示
+/// 1行のバイト数は8バイトを単位として、widthに収まる数 (8、16、32バイト) とする
+fn print_hexdump(addr: usize, buf: &[u8], width: usize) {
+    let addr_width = format!("{:#010x}", addr + buf.len()).len();
+    // 1行の長さは「アドレス:  」「 |」「|」の分のアドレスの桁数+4と、
+    // 8バイトごとに16進数24文字、グループ間の空白1文字、ASCII8文字の33文字
+    let fit = layout::per_line(width.saturating_sub(addr_width + 4), 33, 0);
+    let per_row = 8 * [4, 2, 1].into_iter().find(|g| *g <= fit).unwrap_or(1);
+
+    for (i, row) in buf.chunks(per_row).enumerate() {
+        let mut hex = String::new();
+        for j in 0..per_row {
+            if j > 0 && j % 8 == 0 {
+                hex.push(' ');
+            }
+            match row.get(j) {
+                Some(b) => hex.push_str(&format!("{b:02x} ")),
+                None => hex.push_str("   "),
+            }
+        }
+
+        let ascii: String = row
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        println!("{:#010x}:  {hex} |{ascii}|", addr + i * per_row);
+    }
+}
+
+/// meditで1ページに表示するバイト数
+pub(crate) const MEDIT_PAGE: usize = 64;
+
+/// backtraceで表示する最大のフレーム数。壊れたRBPの連鎖で無限に続かないようにする
+pub(crate) const MAX_FRAMES: usize = 64;
+
+/// クラッシュレポートで表示するフレーム数の上限
+const CRASH_FRAMES: usize = 20;
+
+/// ブレークポイントのログの最大サイズの初期値 (バイト)
+pub(crate) const LOG_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// meditの「オフセット=値[:サイズ]」をパースし、オフセットとリトルエンディアンのバイト列を返す
+/// サイズは1、2、4、8バイトで、省略した場合は1バイト
+fn parse_medit_edit(s: &str) -> Result<(usize, Vec<u8>), String> {
+    let parse = |s: &str| match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse::<u64>(),
+    };
+
+    let (off, val) = s
+        .split_once('=')
+        .ok_or("オフセット=値の形式で指定してください (例：10=0x41)")?;
+    let (val, size) = match val.split_once(':') {
+        Some((val, size)) => (val, size.parse::<usize>().unwrap_or(0)),
+        None => (val, 1),
+    };
+    if ![1, 2, 4, 8].contains(&size) {
+        return Err("サイズは1、2、4、8のいずれかを指定してください".into());
+    }
+
+    let off = parse(off.trim()).map_err(|e| format!("オフセット変換エラー：{e}"))?;
+    let val = parse(val.trim()).map_err(|e| format!("値変換エラー：{e}"))?;
+    if size < 8 && val >> (size * 8) != 0 {
+        return Err(format!("値が{size}バイトに収まりません：{val:#x}"));
+    }
+
+    Ok((off as usize, val.to_le_bytes()[..size].to_vec()))
+}
+
+/// 0xから始まる16進数をパース。失敗した場合はメッセージを表示してNoneを返す
+fn parse_hex(s: &str) -> Option<u64> {
+    let hex = match s.strip_prefix("0x") {
+        Some(hex) => hex,
+        None => {
+            eprintln!("<<アドレスは16進数でのみ指定可能です：{s}>>");
+            return None;
+        }
+    };
+
+    match u64::from_str_radix(hex, 16) {
+        Ok(val) => Some(val),
+        Err(e) => {
+            eprintln!("<<アドレス変換エラー：{}>>", e);
+            None
+        }
+    }
+}
+
+/// バイト列を16進数の文字列に変換
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 逆アセンブルした命令を1行で表示。ripの命令には=>を付け、末尾にnoteを付ける
+fn print_insn(insn: &disas::Insn, rip: u64, note: &str) {
+    let mark = if insn.addr == rip { "=>" } else { "  " };
+    println!(
+        "{mark} {:#x}:  {:<30} {}{note}",
+        insn.addr,
+        hex_bytes(&insn.bytes),
+        format!("{} {}", insn.mnemonic, insn.op_str).trim_end()
+    );
+}
+
+/// y/nで確認。yの場合はtrueを返す
+fn confirm(msg: &str) -> bool {
+    print!("{msg} (y/n) ");
+    let _ = io::stdout().flush();
+
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(_) => matches!(line.trim(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/// on/offを真偽値に変換
+fn parse_on_off(s: &str) -> Option<bool> {
+    match s {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// プログラムのクラッシュを示すシグナルか
+fn is_fatal_signal(sig: Signal) -> bool {
+    matches!(
+        sig,
+        Signal::SIGSEGV | Signal::SIGABRT | Signal::SIGBUS | Signal::SIGILL | Signal::SIGFPE
+    )
+}
+
+/// /proc/<pid>/mapsを読み込む
+fn read_maps(pid: Pid) -> std::io::Result<Vec<MapEntry>> {
+    let maps = fs::read_to_string(format!("/proc/{pid}/maps"))?;
+    Ok(maps.lines().filter_map(MapEntry::parse).collect())
+}
+
+/// 動的リンカ (インタプリタ) がマッピングされている範囲
+struct Interp {
+    path: String,
+    start: u64,
+    end: u64,
+}
+
+/// AT_BASEを含むマッピングのファイルを動的リンカとし、その範囲を取得
+/// 静的リンクの実行ファイルなど、AT_BASEが0の場合はNone
+fn interp_range(pid: Pid, maps: &[MapEntry]) -> Option<Interp> {
+    let base = auxv_value(pid, libc::AT_BASE).filter(|b| *b != 0)?;
+    let path = &maps.iter().find(|m| m.start <= base && base < m.end)?.path;
+    let ld = maps.iter().filter(|m| m.path == *path);
+    Some(Interp {
+        path: path.clone(),
+        start: ld.clone().map(|m| m.start).min()?,
+        end: ld.map(|m| m.end).max()?,
+    })
+}
+
+/// /proc/<pid>/auxvから補助ベクタのtyの値を取得
+fn auxv_value(pid: Pid, ty: u64) -> Option<u64> {
+    let auxv = fs::read(format!("/proc/{pid}/auxv")).ok()?;
+    auxv.chunks_exact(16)
+        .map(|c| {
+            let key = u64::from_le_bytes(c[..8].try_into().unwrap());
+            let val = u64::from_le_bytes(c[8..].try_into().unwrap());
+            (key, val)
+        })
+        .find(|(key, _)| *key == ty)
+        .map(|(_, val)| val)
+}
+
+/// 子プロセスの実行ファイルのパス
+fn exe_path(pid: Pid) -> String {
+    fs::read_link(format!("/proc/{pid}/exe"))
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// pathのファイルがマッピングされているベースアドレス
+fn load_base(maps: &[MapEntry], path: &str) -> Option<u64> {
+    maps.iter()
+        .find(|m| m.path == path)
+        .map(|m| m.start - m.offset)
+}
+
+/// /proc/<pid>/mapsからaddrを含むマッピングを取得
+fn find_map(pid: Pid, addr: u64) -> Option<MapEntry> {
+    read_maps(pid)
+        .ok()?
+        .into_iter()
+        .find(|m| m.start <= addr && addr < m.end)
+}
+
+/// マッピングの比較に使うキー
+/// ファイルのマッピングはパス、パーミッション、オフセットで、
+/// 匿名マッピングはパーミッションで対応付け、同じ値が複数ある場合は出現順で区別する
+fn map_keys(maps: &[MapEntry]) -> Vec<(String, &MapEntry)> {
+    let mut count: HashMap<String, usize> = HashMap::new();
+    maps.iter()
+        .map(|m| {
+            let key = if m.path.is_empty() {
+                format!("[anon] {}", m.perms)
+            } else {
+                format!("{} {} {:#x}", m.path, m.perms, m.offset)
+            };
+            let n = count.entry(key.clone()).or_insert(0);
+            *n += 1;
+            (format!("{key} #{n}"), m)
+        })
+        .collect()
+}
+
+/// 2つのマッピングの差分を表示
+fn print_maps_diff(old: &[MapEntry], new: &[MapEntry]) {
+    let old_keys = map_keys(old);
+    let new_keys = map_keys(new);
+    let new_map: HashMap<&str, &MapEntry> =
+        new_keys.iter().map(|(k, m)| (k.as_str(), *m)).collect();
+    let mut changed = false;
+
+    for (key, o) in old_keys.iter() {
+        match new_map.get(key.as_str()) {
+            Some(n) if n.start != o.start || n.end != o.end => {
+                let delta = n.start as i64 - o.start as i64;
+                let sign = if delta < 0 { "-" } else { "+" };
+                println!(
+                    "moved   {key}: {:#x} -> {:#x} ({sign}{:#x})",
+                    o.start,
+                    n.start,
+                    delta.unsigned_abs()
+                );
+                if n.end - n.start != o.end - o.start {
+                    println!(
+                        "        サイズ：{:#x} -> {:#x}",
+                        o.end - o.start,
+                        n.end - n.start
+                    );
+                }
+                changed = true;
+            }
+            Some(_) => (),
+            None => {
+                println!("removed {o}");
+                changed = true;
+            }
+        }
+    }
+
+    let old_map: HashMap<&str, &MapEntry> =
+        old_keys.iter().map(|(k, m)| (k.as_str(), *m)).collect();
+    for (key, n) in new_keys.iter() {
+        if !old_map.contains_key(key.as_str()) {
+            println!("added   {n}");
+            changed = true;
+        }
+    }
+
+    if !changed {
+        println!("<<マッピングに差分はありません>>");
+    }
+}
+
+/// /proc/<pid>/statusから指定した項目の値 (kB) を取得
+fn read_proc_status(pid: Pid, key: &str) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status.lines().find_map(|line| {
+        let value = line.strip_prefix(key)?.strip_prefix(':')?;
+        value.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// /proc/<pid>/mapsから大きい順にn個の匿名マッピングを取得
+fn largest_anon_maps(pid: Pid, n: usize) -> Vec<MapEntry> {
+    let mut anon: Vec<MapEntry> = match read_maps(pid) {
+        // パス名がない、もしくは[heap]のマッピングを匿名とみなす
+        Ok(maps) => maps
+            .into_iter()
+            .filter(|m| m.path.is_empty() || m.path == "[heap]")
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+
+    anon.sort_by_key(|m| std::cmp::Reverse(m.end - m.start));
+    anon.truncate(n);
+    anon
 }
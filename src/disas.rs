@@ -0,0 +1,211 @@
+//! 逆アセンブル (disassemble)
+
+use capstone::prelude::*;
+
+/// 逆アセンブルした命令
+pub struct Insn {
+    pub addr: u64,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub op_str: String,
+}
+
+/// 逆アセンブルの構文 (set disassembly-flavor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    Intel,
+    Att,
+}
+
+impl Syntax {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "intel" => Some(Syntax::Intel),
+            "att" => Some(Syntax::Att),
+            _ => None,
+        }
+    }
+}
+
+/// codeをaddrから始まる機械語として、最大count命令を逆アセンブル
+/// 不正な命令に到達した場合は、そこまでの命令を返す
+/// 命令の解析 (branch_targetやpush rbpの検出など) にはIntel構文を使用すること
+pub fn decode(code: &[u8], addr: u64, count: usize, syntax: Syntax) -> Result<Vec<Insn>, String> {
+    let syntax = match syntax {
+        Syntax::Intel => arch::x86::ArchSyntax::Intel,
+        Syntax::Att => arch::x86::ArchSyntax::Att,
+    };
+    let cs = Capstone::new()
+        .x86()
+        .mode(arch::x86::ArchMode::Mode64)
+        .syntax(syntax)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let insns = cs
+        .disasm_count(code, addr, count)
+        .map_err(|e| e.to_string())?;
+
+    Ok(insns
+        .iter()
+        .map(|i| Insn {
+            addr: i.address(),
+            bytes: i.bytes().to_vec(),
+            mnemonic: i.mnemonic().unwrap_or("").to_string(),
+            op_str: i.op_str().unwrap_or("").to_string(),
+        })
+        .collect())
+}
+
+/// addrから始まるcodeの先頭lenバイトを含む命令全体の長さ
+/// patchで書き換えた後に途中までの命令が残らないよう、nopで埋める長さを求める
+/// lenバイトに達する前にデコードできなくなった場合はNone
+pub fn cover_len(code: &[u8], addr: u64, len: usize) -> Option<usize> {
+    // 命令は1バイト以上のため、len個の命令をデコードすれば足りる
+    let insns = decode(code, addr, len, Syntax::Intel).ok()?;
+    let mut total = 0;
+    for insn in insns.iter() {
+        total += insn.bytes.len();
+        if total >= len {
+            return Some(total);
+        }
+    }
+    None
+}
+
+// EFLAGSのビット
+const CF: u64 = 1 << 0;
+const PF: u64 = 1 << 2;
+const ZF: u64 = 1 << 6;
+const SF: u64 = 1 << 7;
+const OF: u64 = 1 << 11;
+
+impl Insn {
+    /// call、jmp、条件分岐の分岐先。レジスタやメモリを経由する間接分岐の場合はNone
+    pub fn branch_target(&self) -> Option<u64> {
+        // bnd jmpのようにプレフィックスが付く場合がある
+        let op = self.mnemonic.rsplit(' ').next()?;
+        if op != "call" && !op.starts_with('j') {
+            return None;
+        }
+        let hex = self.op_str.strip_prefix("0x")?;
+        u64::from_str_radix(hex, 16).ok()
+    }
+}
+
+/// 条件分岐命令mnemonicが、eflagsとrcxの値で分岐するか。条件分岐でない場合はNone
+pub fn jcc_taken(mnemonic: &str, eflags: u64, rcx: u64) -> Option<bool> {
+    let f = |bit: u64| eflags & bit != 0;
+    let taken = match mnemonic {
+        "jo" => f(OF),
+        "jno" => !f(OF),
+        "jb" | "jc" | "jnae" => f(CF),
+        "jae" | "jnb" | "jnc" => !f(CF),
+        "je" | "jz" => f(ZF),
+        "jne" | "jnz" => !f(ZF),
+        "jbe" | "jna" => f(CF) || f(ZF),
+        "ja" | "jnbe" => !f(CF) && !f(ZF),
+        "js" => f(SF),
+        "jns" => !f(SF),
+        "jp" | "jpe" => f(PF),
+        "jnp" | "jpo" => !f(PF),
+        "jl" | "jnge" => f(SF) != f(OF),
+        "jge" | "jnl" => f(SF) == f(OF),
+        "jle" | "jng" => f(ZF) || f(SF) != f(OF),
+        "jg" | "jnle" => !f(ZF) && f(SF) == f(OF),
+        "jrcxz" => rcx == 0,
+        "jecxz" => rcx as u32 == 0,
+        _ => return None,
+    };
+    Some(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 分岐する場合と分岐しない場合のEFLAGSとRCX
+    fn check(mnemonic: &str, taken: (u64, u64), not_taken: (u64, u64)) {
+        assert_eq!(
+            jcc_taken(mnemonic, taken.0, taken.1),
+            Some(true),
+            "{mnemonic}"
+        );
+        assert_eq!(
+            jcc_taken(mnemonic, not_taken.0, not_taken.1),
+            Some(false),
+            "{mnemonic}"
+        );
+    }
+
+    #[test]
+    fn jcc_flags() {
+        check("je", (ZF, 0), (0, 0));
+        check("jne", (0, 0), (ZF, 0));
+        check("jb", (CF, 0), (0, 0));
+        check("jae", (0, 0), (CF, 0));
+        check("jbe", (ZF, 0), (0, 0));
+        check("jbe", (CF, 0), (0, 0));
+        check("ja", (0, 0), (CF, 0));
+        check("ja", (0, 0), (ZF, 0));
+        check("js", (SF, 0), (0, 0));
+        check("jns", (0, 0), (SF, 0));
+        check("jp", (PF, 0), (0, 0));
+        check("jnp", (0, 0), (PF, 0));
+        check("jo", (OF, 0), (0, 0));
+        check("jno", (0, 0), (OF, 0));
+    }
+
+    #[test]
+    fn jcc_signed() {
+        check("jl", (SF, 0), (SF | OF, 0));
+        check("jl", (OF, 0), (0, 0));
+        check("jge", (SF | OF, 0), (SF, 0));
+        check("jge", (0, 0), (OF, 0));
+        check("jle", (ZF, 0), (0, 0));
+        check("jle", (SF, 0), (SF | OF, 0));
+        check("jg", (0, 0), (ZF, 0));
+        check("jg", (SF | OF, 0), (OF, 0));
+    }
+
+    #[test]
+    fn jcc_rcx() {
+        check("jrcxz", (0, 0), (0, 1 << 32));
+        check("jecxz", (0, 1 << 32), (0, 1));
+    }
+
+    #[test]
+    fn jcc_aliases() {
+        check("jz", (ZF, 0), (0, 0));
+        check("jnz", (0, 0), (ZF, 0));
+        check("jc", (CF, 0), (0, 0));
+        check("jnc", (0, 0), (CF, 0));
+        check("jnae", (CF, 0), (0, 0));
+        check("jnb", (0, 0), (CF, 0));
+        check("jna", (CF, 0), (0, 0));
+        check("jnbe", (0, 0), (CF, 0));
+        check("jpe", (PF, 0), (0, 0));
+        check("jpo", (0, 0), (PF, 0));
+        check("jnge", (SF, 0), (0, 0));
+        check("jnl", (0, 0), (SF, 0));
+        check("jng", (ZF, 0), (0, 0));
+        check("jnle", (0, 0), (ZF, 0));
+    }
+
+    #[test]
+    fn cover_len_rounds_up_to_insn() {
+        // push rbp; mov rbp, rsp; sub rsp, 0x10
+        let code = [0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x10];
+        assert_eq!(cover_len(&code, 0x1000, 1), Some(1));
+        assert_eq!(cover_len(&code, 0x1000, 2), Some(4));
+        assert_eq!(cover_len(&code, 0x1000, 4), Some(4));
+        assert_eq!(cover_len(&code, 0x1000, 5), Some(8));
+        // 最後の命令が途中で切れている場合
+        assert_eq!(cover_len(&code[..6], 0x1000, 5), None);
+    }
+
+    #[test]
+    fn not_jcc() {
+        assert_eq!(jcc_taken("jmp", 0, 0), None);
+        assert_eq!(jcc_taken("call", ZF, 0), None);
+    }
+}
@@ -0,0 +1,146 @@
+//! 実行ファイル (ELF) の解析
+
+use object::{
+    elf, read::elf::ElfFile64, Object, ObjectSymbol, ObjectSymbolTable, RelocationFlags,
+    RelocationTarget, SymbolKind,
+};
+use std::{collections::HashMap, fs};
+
+/// 関数のシンボル
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64, // ファイル上のアドレス
+    pub size: u64,
+    pub ifunc: bool, // STT_GNU_IFUNCか
+}
+
+/// 実行ファイルの関数のシンボル一覧
+pub struct Symbols {
+    funcs: Vec<Symbol>,        // アドレス順
+    got: HashMap<String, u64>, // 関数名と、そのアドレスが書き込まれるGOTのアドレス
+    pie: bool,                 // ET_DYNか
+    entry: u64,                // エントリポイント (ファイル上のアドレス)
+}
+
+impl Symbols {
+    /// filenameの.symtabと.dynsymから関数のシンボルを読み込む
+    /// 名前が重複する場合は.symtabを優先し、.dynsymの非デフォルトのバージョンは無視
+    pub fn load(filename: &str) -> Result<Self, String> {
+        let data = fs::read(filename).map_err(|e| format!("{filename}：{e}"))?;
+        let file = ElfFile64::<object::Endianness>::parse(&*data)
+            .map_err(|e| format!("{filename}：{e}"))?;
+        let endian = file.endian();
+        let versions = file
+            .elf_section_table()
+            .versions(endian, file.data())
+            .ok()
+            .flatten();
+
+        let mut funcs: HashMap<String, Symbol> = HashMap::new();
+        let dynsyms = file.dynamic_symbols().map(|s| (s, true));
+        for (sym, dynamic) in file.symbols().map(|s| (s, false)).chain(dynsyms) {
+            if sym.kind() != SymbolKind::Text || sym.is_undefined() || sym.address() == 0 {
+                continue;
+            }
+            if dynamic {
+                if let Some(v) = &versions {
+                    if v.version_index(endian, sym.index()).is_hidden() {
+                        continue;
+                    }
+                }
+            }
+
+            if let Ok(name) = sym.name() {
+                if !name.is_empty() {
+                    funcs.entry(name.to_string()).or_insert(Symbol {
+                        name: name.to_string(),
+                        addr: sym.address(),
+                        size: sym.size(),
+                        ifunc: sym.elf_symbol().st_type() == elf::STT_GNU_IFUNC,
+                    });
+                }
+            }
+        }
+
+        let mut funcs: Vec<Symbol> = funcs.into_values().collect();
+        funcs.sort_by(|a, b| a.addr.cmp(&b.addr).then_with(|| a.name.cmp(&b.name)));
+
+        Ok(Symbols {
+            funcs,
+            got: got_slots(&file),
+            pie: file.elf_header().e_type.get(endian) == elf::ET_DYN,
+            entry: file.elf_header().e_entry.get(endian),
+        })
+    }
+
+    /// シンボルが1つもないか (strip済み)
+    pub fn is_empty(&self) -> bool {
+        self.funcs.is_empty()
+    }
+
+    /// 位置独立実行形式 (ET_DYN) か。アドレスはロードされたベースアドレスからの相対になる
+    pub fn is_pie(&self) -> bool {
+        self.pie
+    }
+
+    /// エントリポイント (ファイル上のアドレス)
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// 名前からシンボルを検索
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.funcs.iter().find(|s| s.name == name)
+    }
+
+    /// 名前からアドレスを検索
+    pub fn lookup(&self, name: &str) -> Option<u64> {
+        self.find(name).map(|s| s.addr)
+    }
+
+    /// addrを含むシンボルと、シンボルの先頭からのオフセット
+    pub fn symbol_at(&self, addr: u64) -> Option<(&Symbol, u64)> {
+        let sym = self
+            .funcs
+            .iter()
+            .rfind(|s| s.addr <= addr && addr < s.addr + s.size.max(1))?;
+        Some((sym, addr - sym.addr))
+    }
+
+    /// 関数nameのアドレスが書き込まれるGOTのアドレス (ファイル上のアドレス)
+    pub fn got_slot(&self, name: &str) -> Option<u64> {
+        self.got.get(name).copied()
+    }
+
+    /// アドレス順のシンボル
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.funcs.iter()
+    }
+}
+
+/// R_X86_64_JUMP_SLOTとR_X86_64_GLOB_DATの再配置から、関数名とGOTのアドレスを取得
+fn got_slots(file: &ElfFile64) -> HashMap<String, u64> {
+    let mut got = HashMap::new();
+    let (relocs, dynsyms) = match (file.dynamic_relocations(), file.dynamic_symbol_table()) {
+        (Some(relocs), Some(dynsyms)) => (relocs, dynsyms),
+        _ => return got,
+    };
+
+    for (offset, reloc) in relocs {
+        let r_type = match reloc.flags() {
+            RelocationFlags::Elf { r_type } => r_type,
+            _ => continue,
+        };
+        if r_type != elf::R_X86_64_JUMP_SLOT && r_type != elf::R_X86_64_GLOB_DAT {
+            continue;
+        }
+
+        if let RelocationTarget::Symbol(idx) = reloc.target() {
+            if let Ok(name) = dynsyms.symbol_by_index(idx).and_then(|s| s.name()) {
+                got.insert(name.to_string(), offset);
+            }
+        }
+    }
+
+    got
+}
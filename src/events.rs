@@ -0,0 +1,208 @@
+//! イベントごとの動作の設定 (info events、set event) と最近のイベントの記録 (events recent)
+//!
+//! 子プロセスの停止の原因となるイベントはすべてEvents::dispatchで記録し、
+//! 設定された動作 (停止、表示して継続、表示せずに継続) に従って処理する。
+
+use nix::sys::signal::Signal;
+use std::{collections::VecDeque, fmt};
+
+/// 記録する最近のイベントの数
+pub const RECENT_MAX: usize = 256;
+
+/// 動作を変更できないイベント
+/// SIGTRAPはステップ実行とブレークポイントに使用し、internalはfinishやuntilの一時ブレークポイント
+const FIXED: &[&str] = &["SIGTRAP", "internal", "exit"];
+
+/// イベントに対する動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Stop,   // 停止
+    Log,    // 表示して実行を継続
+    Ignore, // 表示せずに実行を継続
+}
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "stop" => Some(Action::Stop),
+            "log" => Some(Action::Log),
+            "ignore" => Some(Action::Ignore),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Action::Stop => "stop",
+            Action::Log => "log",
+            Action::Ignore => "ignore",
+        };
+        f.pad(s)
+    }
+}
+
+/// 記録したイベント
+struct Record {
+    time: String, // runからの経過時間
+    class: String,
+    detail: String,
+    action: Action,
+}
+
+/// イベントごとの動作と最近のイベント
+pub struct Events {
+    policy: Vec<(String, Action)>, // イベントの種類と動作。info eventsで表示する順
+    recent: VecDeque<Record>,
+}
+
+impl Events {
+    pub fn new() -> Self {
+        let mut policy: Vec<(String, Action)> = [
+            ("breakpoint", Action::Stop),
+            ("internal", Action::Stop),
+            ("syscall", Action::Log),
+            ("fork", Action::Ignore),
+            ("vfork", Action::Ignore),
+            ("exec", Action::Stop),
+            ("library", Action::Ignore),
+            ("exit", Action::Stop),
+        ]
+        .iter()
+        .map(|(c, a)| (c.to_string(), *a))
+        .collect();
+
+        // SIGKILLは停止せずに終了するため対象外
+        for sig in Signal::iterator().filter(|s| *s != Signal::SIGKILL) {
+            policy.push((sig.as_str().to_string(), Action::Stop));
+        }
+
+        Events {
+            policy,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// classの動作を変更
+    pub fn set(&mut self, class: &str, action: Action) -> Result<(), String> {
+        if FIXED.contains(&class) {
+            return Err(format!("{class}の動作は変更できません"));
+        }
+        match self.policy.iter_mut().find(|(c, _)| c == class) {
+            Some((_, a)) => {
+                *a = action;
+                Ok(())
+            }
+            None => Err(format!("不明なイベントです：{class}")),
+        }
+    }
+
+    /// イベントを記録し、設定された動作を返す
+    /// timeはrunからの経過時間、detailはevents recentで表示する詳細
+    pub fn dispatch(&mut self, time: String, class: &str, detail: String) -> Action {
+        let action = self
+            .policy
+            .iter()
+            .find(|(c, _)| c == class)
+            .map_or(Action::Stop, |(_, a)| *a);
+
+        if self.recent.len() == RECENT_MAX {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Record {
+            time,
+            class: class.to_string(),
+            detail,
+            action,
+        });
+
+        action
+    }
+
+    /// info eventsを実行
+    pub fn print_policy(&self) {
+        for (class, action) in self.policy.iter() {
+            let fixed = if FIXED.contains(&class.as_str()) {
+                "  (固定)"
+            } else {
+                ""
+            };
+            println!("{class:<12} {action}{fixed}");
+        }
+    }
+
+    /// events recentを実行。最近のn個のイベントを古い順に表示
+    pub fn print_recent(&self, n: usize) {
+        if self.recent.is_empty() {
+            println!("<<記録されたイベントはありません>>");
+            return;
+        }
+
+        let skip = self.recent.len().saturating_sub(n);
+        for r in self.recent.iter().skip(skip) {
+            println!("+{} {:<12} {:<6} {}", r.time, r.class, r.action, r.detail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action_of(events: &Events, class: &str) -> Option<Action> {
+        events
+            .policy
+            .iter()
+            .find(|(c, _)| c == class)
+            .map(|(_, a)| *a)
+    }
+
+    #[test]
+    fn set_rejects_fixed() {
+        let mut events = Events::new();
+        for class in FIXED {
+            assert!(events.set(class, Action::Ignore).is_err());
+        }
+        assert_eq!(action_of(&events, "SIGTRAP"), Some(Action::Stop));
+        assert_eq!(action_of(&events, "internal"), Some(Action::Stop));
+        assert_eq!(action_of(&events, "exit"), Some(Action::Stop));
+    }
+
+    #[test]
+    fn set_changes_action() {
+        let mut events = Events::new();
+        assert_eq!(events.set("SIGUSR1", Action::Log), Ok(()));
+        assert_eq!(events.set("library", Action::Stop), Ok(()));
+        assert_eq!(action_of(&events, "SIGUSR1"), Some(Action::Log));
+        assert_eq!(action_of(&events, "library"), Some(Action::Stop));
+        assert!(events.set("nosuch", Action::Stop).is_err());
+    }
+
+    #[test]
+    fn dispatch_defaults() {
+        let mut events = Events::new();
+        let mut dispatch = |class: &str| events.dispatch(String::new(), class, String::new());
+        assert_eq!(dispatch("breakpoint"), Action::Stop);
+        assert_eq!(dispatch("syscall"), Action::Log);
+        assert_eq!(dispatch("fork"), Action::Ignore);
+        assert_eq!(dispatch("library"), Action::Ignore);
+        assert_eq!(dispatch("SIGSEGV"), Action::Stop);
+        // 不明なイベントは停止する
+        assert_eq!(dispatch("nosuch"), Action::Stop);
+    }
+
+    #[test]
+    fn recent_evicts_oldest() {
+        let mut events = Events::new();
+        for i in 0..RECENT_MAX + 10 {
+            events.dispatch(String::new(), "syscall", i.to_string());
+        }
+        assert_eq!(events.recent.len(), RECENT_MAX);
+        assert_eq!(events.recent.front().unwrap().detail, "10");
+        assert_eq!(
+            events.recent.back().unwrap().detail,
+            (RECENT_MAX + 9).to_string()
+        );
+    }
+}
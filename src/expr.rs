@@ -0,0 +1,316 @@
+//! アドレスや数値の式の評価
+//!
+//! 数値 (16進数は0x、それ以外は10進数)、$から始まる変数、シンボル名、
+//! 四則演算と括弧をサポートする。計算はu64の折り返し演算で行う。
+
+use std::collections::HashMap;
+
+/// 式を評価。変数の値はresolveで、シンボル名 (mainなど) のアドレスはsymbolで解決する
+pub fn eval(
+    expr: &str,
+    resolve: &dyn Fn(&str) -> Result<u64, String>,
+    symbol: &dyn Fn(&str) -> Result<u64, String>,
+) -> Result<u64, String> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+        resolve,
+        symbol,
+    };
+
+    let val = parser.expr()?;
+    if parser.pos < tokens.len() {
+        return Err(format!("不正なトークンです：{}", tokens[parser.pos]));
+    }
+    Ok(val)
+}
+
+/// $nameの値を解決。レジスタ、値の履歴 ($_は直前の結果、$1は1番目の結果)、
+/// set $fooで定義した変数の順に検索する
+/// regはnameがレジスタ名の場合にその値 (実行中でない場合はエラー) を返し、それ以外はNoneを返す
+/// set $fooではレジスタ名を変数名に使用できないため、変数がレジスタを隠すことはない
+pub fn lookup_var(
+    name: &str,
+    reg: &dyn Fn(&str) -> Option<Result<u64, String>>,
+    history: &[u64],
+    vars: &HashMap<String, u64>,
+) -> Result<u64, String> {
+    if let Some(val) = reg(name) {
+        return val;
+    }
+
+    let val = if name == "_" {
+        history.last()
+    } else if let Ok(n) = name.parse::<usize>() {
+        n.checked_sub(1).and_then(|i| history.get(i))
+    } else {
+        vars.get(name)
+    };
+    val.copied().ok_or(format!("未定義の変数です：${name}"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(u64),
+    Var(String),
+    Sym(String),
+    Op(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{n:#x}"),
+            Token::Var(v) => write!(f, "${v}"),
+            Token::Sym(s) => write!(f, "{s}"),
+            Token::Op(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+/// 字句解析
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = expr.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '$' || c == '_' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            tokens.push(parse_word(&word)?);
+        } else {
+            return Err(format!("不正な文字です：{c}"));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// 数値、変数、シンボル名のトークンに変換。数字以外から始まる語はシンボル名とする
+fn parse_word(word: &str) -> Result<Token, String> {
+    if let Some(name) = word.strip_prefix('$') {
+        if name.is_empty() {
+            return Err("変数名がありません".into());
+        }
+        return Ok(Token::Var(name.to_string()));
+    }
+    if !word.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Token::Sym(word.to_string()));
+    }
+
+    let num = if let Some(hex) = word.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16)
+    } else {
+        word.parse::<u64>()
+    };
+
+    match num {
+        Ok(n) => Ok(Token::Num(n)),
+        Err(e) => Err(format!("数値変換エラー：{word}：{e}")),
+    }
+}
+
+/// 再帰下降パーサ
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    resolve: &'a dyn Fn(&str) -> Result<u64, String>,
+    symbol: &'a dyn Fn(&str) -> Result<u64, String>,
+}
+
+impl<'a> Parser<'a> {
+    /// expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<u64, String> {
+        let mut val = self.term()?;
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            let rhs = self.term()?;
+            val = if *op == '+' {
+                val.wrapping_add(rhs)
+            } else {
+                val.wrapping_sub(rhs)
+            };
+        }
+        Ok(val)
+    }
+
+    /// term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<u64, String> {
+        let mut val = self.unary()?;
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            let rhs = self.unary()?;
+            val = if *op == '*' {
+                val.wrapping_mul(rhs)
+            } else {
+                val.checked_div(rhs).ok_or("0で除算しました")?
+            };
+        }
+        Ok(val)
+    }
+
+    /// unary := '-' unary | primary
+    fn unary(&mut self) -> Result<u64, String> {
+        if let Some(Token::Op('-')) = self.tokens.get(self.pos) {
+            self.pos += 1;
+            return Ok(self.unary()?.wrapping_neg());
+        }
+        self.primary()
+    }
+
+    /// primary := num | var | sym | '(' expr ')'
+    fn primary(&mut self) -> Result<u64, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or("式が途中で終わっています")?;
+        self.pos += 1;
+
+        match token {
+            Token::Num(n) => Ok(*n),
+            Token::Var(v) => (self.resolve)(v),
+            Token::Sym(s) => (self.symbol)(s),
+            Token::Op('(') => {
+                let val = self.expr()?;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Op(')')) => {
+                        self.pos += 1;
+                        Ok(val)
+                    }
+                    _ => Err("括弧が閉じていません".into()),
+                }
+            }
+            t => Err(format!("不正なトークンです：{t}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_vars(name: &str) -> Result<u64, String> {
+        Err(format!("未定義の変数です：${name}"))
+    }
+
+    fn no_syms(name: &str) -> Result<u64, String> {
+        Err(format!("シンボル '{name}' が見つかりません"))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("2 + 3 * 4", &no_vars, &no_syms), Ok(14));
+        assert_eq!(eval("(2 + 3) * 4", &no_vars, &no_syms), Ok(20));
+        assert_eq!(eval("20 - 12 / 4", &no_vars, &no_syms), Ok(17));
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(eval("10 - 3 - 2", &no_vars, &no_syms), Ok(5));
+        assert_eq!(eval("100 / 10 / 5", &no_vars, &no_syms), Ok(2));
+        assert_eq!(eval("0x10 - 0x20 + 0x10", &no_vars, &no_syms), Ok(0));
+    }
+
+    #[test]
+    fn negative() {
+        assert_eq!(eval("-2 * 3", &no_vars, &no_syms), Ok(-6i64 as u64));
+        assert_eq!(
+            eval("0x1000 - 0x1008", &no_vars, &no_syms),
+            Ok(-8i64 as u64)
+        );
+        assert_eq!(eval("--5", &no_vars, &no_syms), Ok(5));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(eval("1 / 0", &no_vars, &no_syms).is_err());
+        assert!(eval("(1 + 2", &no_vars, &no_syms).is_err());
+        assert!(eval("1 2", &no_vars, &no_syms).is_err());
+        assert!(eval("$foo", &no_vars, &no_syms).is_err());
+    }
+
+    /// raxのみをレジスタとし、runningがfalseの場合はレジスタを使用できない
+    fn rax(running: bool) -> impl Fn(&str) -> Option<Result<u64, String>> {
+        move |name| {
+            (name == "rax").then(|| {
+                if running {
+                    Ok(0x10)
+                } else {
+                    Err("レジスタはプログラム実行中のみ使用可能です".to_string())
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn symbols() {
+        let sym = |name: &str| match name {
+            "main" => Ok(0x5555_5555_5147),
+            "_start" => Ok(0x5555_5555_5040),
+            _ => no_syms(name),
+        };
+        assert_eq!(eval("main + 0x10", &no_vars, &sym), Ok(0x5555_5555_5157));
+        assert_eq!(eval("main - _start", &no_vars, &sym), Ok(0x107));
+        assert!(eval("foo + 1", &no_vars, &sym).is_err());
+        // 数字から始まる語はシンボルではなく数値
+        assert!(eval("1abc", &no_vars, &sym).is_err());
+    }
+
+    #[test]
+    fn history() {
+        let history = [0x100, 0x200, 0x300];
+        let vars = HashMap::new();
+        let reg = rax(true);
+        let var = |name: &str| lookup_var(name, &reg, &history, &vars);
+
+        assert_eq!(var("1"), Ok(0x100));
+        assert_eq!(var("3"), Ok(0x300));
+        assert_eq!(var("_"), Ok(0x300));
+        assert!(var("0").is_err());
+        assert!(var("4").is_err());
+        assert_eq!(eval("$_ - $1", &var, &no_syms), Ok(0x200));
+    }
+
+    #[test]
+    fn history_empty() {
+        let vars = HashMap::new();
+        let reg = rax(true);
+        assert!(lookup_var("_", &reg, &[], &vars).is_err());
+    }
+
+    #[test]
+    fn user_vars() {
+        let vars = HashMap::from([("base".to_string(), 0x5555_0000)]);
+        let reg = rax(true);
+        let var = |name: &str| lookup_var(name, &reg, &[], &vars);
+
+        assert_eq!(eval("$base + 0x1139", &var, &no_syms), Ok(0x5555_1139));
+        assert_eq!(eval("$base + $rax * 2", &var, &no_syms), Ok(0x5555_0020));
+    }
+
+    #[test]
+    fn register_shadows_var() {
+        // set $raxは拒否されるが、変数が存在してもレジスタが優先される
+        let vars = HashMap::from([("rax".to_string(), 0x99)]);
+        let history = [0x99];
+        let running = rax(true);
+        assert_eq!(lookup_var("rax", &running, &history, &vars), Ok(0x10));
+
+        // 実行中でない場合も変数にはフォールバックしない
+        let stopped = rax(false);
+        assert!(lookup_var("rax", &stopped, &history, &vars).is_err());
+        assert_eq!(lookup_var("_", &stopped, &history, &vars), Ok(0x99));
+    }
+}
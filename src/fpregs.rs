@@ -0,0 +1,80 @@
+//! 浮動小数点レジスタ (x87、SSE) の取得と表示 (registers float)
+
+use nix::{errno::Errno, libc, unistd::Pid};
+use std::mem;
+
+/// PTRACE_GETFPREGSで浮動小数点レジスタを取得
+/// 失敗した場合はPTRACE_GETREGSET (NT_PRFPREG) で取得する
+pub fn get(pid: Pid) -> Result<libc::user_fpregs_struct, Errno> {
+    let mut fp: libc::user_fpregs_struct = unsafe { mem::zeroed() };
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETFPREGS,
+            pid.as_raw(),
+            0,
+            &mut fp as *mut libc::user_fpregs_struct,
+        )
+    };
+    if ret >= 0 {
+        return Ok(fp);
+    }
+
+    let mut iov = libc::iovec {
+        iov_base: &mut fp as *mut libc::user_fpregs_struct as *mut libc::c_void,
+        iov_len: mem::size_of::<libc::user_fpregs_struct>(),
+    };
+    let ret = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETREGSET,
+            pid.as_raw(),
+            libc::NT_PRFPREG,
+            &mut iov as *mut libc::iovec,
+        )
+    };
+    if ret < 0 {
+        return Err(Errno::last());
+    }
+    Ok(fp)
+}
+
+/// x87のスタックレジスタとXMM0〜XMM15を表示
+pub fn print(fp: &libc::user_fpregs_struct) {
+    println!(
+        "FCW: {:#06x}, FSW: {:#06x}, FTW: {:#06x}, MXCSR: {:#010x}",
+        fp.cwd, fp.swd, fp.ftw, fp.mxcsr
+    );
+
+    // st_spaceは1レジスタあたり16バイトで、先頭10バイトが80ビットの値
+    for (i, st) in fp.st_space.chunks(4).enumerate() {
+        let mant = st[0] as u64 | (st[1] as u64) << 32;
+        let se = st[2] as u16;
+        println!("ST{i}: {se:#06x}{mant:016x}  {:?}", ext80_to_f64(se, mant));
+    }
+
+    // xmm_spaceは1レジスタあたり16バイト
+    for (i, xmm) in fp.xmm_space.chunks(4).enumerate() {
+        let lo = xmm[0] as u64 | (xmm[1] as u64) << 32;
+        let hi = xmm[2] as u64 | (xmm[3] as u64) << 32;
+        let name = format!("XMM{i}");
+        println!(
+            "{name:>5}: {hi:#018x}{lo:016x}  [{:?}, {:?}]",
+            f64::from_bits(lo),
+            f64::from_bits(hi)
+        );
+    }
+}
+
+/// 80ビット拡張倍精度浮動小数点数をf64に変換
+/// seは符号と指数、mantは明示的な整数ビットを含む仮数
+fn ext80_to_f64(se: u16, mant: u64) -> f64 {
+    let sign = if se & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (se & 0x7fff) as i32;
+    let val = match exp {
+        0 if mant == 0 => 0.0,
+        0x7fff if mant << 1 == 0 => f64::INFINITY,
+        0x7fff => f64::NAN,
+        // 仮数は整数ビットを含む64ビットのため、2^63で割った値が1以上2未満
+        _ => mant as f64 * 2f64.powi(exp.max(1) - 16383 - 63),
+    };
+    sign * val
+}
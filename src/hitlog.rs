@@ -0,0 +1,100 @@
+//! ブレークポイントごとのヒットのログ (break 0x8000 log hits.log)
+//!
+//! ヒットするたびに停止せず1行をファイルに追記する。
+//! ファイルは最初のヒットで開き、子プロセスの終了時に閉じる。
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    time::{Duration, Instant},
+};
+
+/// バッファをファイルに書き出す間隔
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 1つのブレークポイントのログ
+pub struct HitLog {
+    path: String,
+    exprs: Vec<String>, // ヒットごとに評価して記録する式
+    file: Option<BufWriter<File>>,
+    size: u64, // ファイルサイズ (書き込んだ分を含む)
+    last_flush: Instant,
+    capped: bool, // サイズの上限に達して記録を止めたか
+}
+
+impl HitLog {
+    pub fn new(path: &str, exprs: &[&str]) -> Self {
+        HitLog {
+            path: path.to_string(),
+            exprs: exprs.iter().map(|e| e.to_string()).collect(),
+            file: None,
+            size: 0,
+            last_flush: Instant::now(),
+            capped: false,
+        }
+    }
+
+    /// 記録する式
+    pub fn exprs(&self) -> &[String] {
+        &self.exprs
+    }
+
+    /// 1行追記。ファイルが開かれていなければ開く
+    /// max_sizeバイトを超える場合は記録を止めて警告を表示
+    pub fn write(&mut self, line: &str, max_size: u64) -> Result<(), String> {
+        if self.capped {
+            return Ok(());
+        }
+
+        if self.file.is_none() {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(|e| format!("{}：{e}", self.path))?;
+            self.size = file.metadata().map(|m| m.len()).unwrap_or(0);
+            self.file = Some(BufWriter::new(file));
+        }
+
+        let len = line.len() as u64 + 1;
+        if self.size + len > max_size {
+            self.capped = true;
+            self.close();
+            eprintln!(
+                "<<{}が上限の{max_size}バイトに達したため、記録を停止しました>>",
+                self.path
+            );
+            return Ok(());
+        }
+
+        let file = self.file.as_mut().unwrap();
+        writeln!(file, "{line}").map_err(|e| format!("{}：{e}", self.path))?;
+        self.size += len;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            let _ = file.flush();
+            self.last_flush = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// ファイルを閉じる。次に書き込む際に再び開く
+    pub fn close(&mut self) {
+        if let Some(mut file) = self.file.take() {
+            let _ = file.flush();
+        }
+    }
+
+    /// info break用の説明
+    pub fn describe(&self) -> String {
+        let mut s = format!("log={}", self.path);
+        if !self.exprs.is_empty() {
+            s.push_str(&format!(" [{}]", self.exprs.join(", ")));
+        }
+        if self.capped {
+            s.push_str(" (上限に到達)");
+        }
+        s
+    }
+}
@@ -0,0 +1,139 @@
+//! 端末幅に合わせた出力の整形
+
+use nix::libc;
+
+/// 端末幅が取得できない場合の幅
+const DEFAULT_WIDTH: usize = 80;
+
+/// 標準出力の端末幅を取得。端末でない場合はCOLUMNS環境変数か80を返す
+pub fn term_width() -> usize {
+    let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut ws) } == 0 && ws.ws_col > 0
+    {
+        return ws.ws_col as usize;
+    }
+
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// 幅cell_widthのセルをsep_width幅の区切りで並べた場合に、widthに収まるセルの数
+/// widthが1セルより狭い場合も1を返す
+pub fn per_line(width: usize, cell_width: usize, sep_width: usize) -> usize {
+    ((width + sep_width) / (cell_width + sep_width).max(1)).max(1)
+}
+
+/// 同じ幅のセルを、widthに収まる数ずつsepで区切って行に並べる
+/// widthが1セルより狭い場合も1行に1セルは表示する
+pub fn columns(cells: &[String], width: usize, sep: &str) -> Vec<String> {
+    let cell_width = cells.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+    let per_line = per_line(width, cell_width, sep.len());
+
+    cells.chunks(per_line).map(|line| line.join(sep)).collect()
+}
+
+/// 長さの異なる項目を2つの空白で区切り、widthを超える場合はindent文字下げて次の行に折り返す
+/// 1項目でwidthを超える場合はその項目のみの行とする
+pub fn wrap(parts: &[String], width: usize, indent: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+    for part in parts.iter().filter(|p| !p.is_empty()) {
+        if line.is_empty() {
+            line.push_str(part);
+        } else if line.chars().count() + 2 + part.chars().count() <= width {
+            line.push_str("  ");
+            line.push_str(part);
+        } else {
+            lines.push(line);
+            line = format!("{}{part}", " ".repeat(indent));
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTHS: [usize; 4] = [60, 80, 120, 200];
+
+    /// registersと同じ形式のセル
+    fn reg_cells() -> Vec<String> {
+        [
+            "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "rip",
+        ]
+        .iter()
+        .map(|name| format!("{name:>3}: {:#016x}", u64::MAX))
+        .collect()
+    }
+
+    #[test]
+    fn columns_fit_width() {
+        let cells = reg_cells();
+        for width in WIDTHS {
+            let lines = columns(&cells, width, ", ");
+            for line in lines.iter() {
+                assert!(line.chars().count() <= width, "{width}: {line}");
+            }
+            assert_eq!(lines.join(", "), cells.join(", "));
+        }
+    }
+
+    #[test]
+    fn columns_use_width() {
+        let cells = reg_cells();
+        let counts: Vec<usize> = WIDTHS
+            .iter()
+            .map(|w| columns(&cells, *w, ", ").len())
+            .collect();
+        assert_eq!(counts, vec![6, 4, 3, 2]);
+    }
+
+    #[test]
+    fn columns_narrow() {
+        let cells = reg_cells();
+        assert_eq!(columns(&cells, 10, ", ").len(), cells.len());
+    }
+
+    #[test]
+    fn wrap_fit_width() {
+        let parts: Vec<String> = [
+            "[1] 0x0000555555555147",
+            "enabled ",
+            "hits=3",
+            "<main>",
+            "if rax == 0x10",
+            "log /tmp/main.log (1234 bytes)",
+            "",
+            "armed",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        for width in WIDTHS {
+            let lines = wrap(&parts, width, 4);
+            for line in lines.iter() {
+                assert!(line.chars().count() <= width, "{width}: {line}");
+            }
+            let words: Vec<&str> = lines.iter().flat_map(|l| l.split_whitespace()).collect();
+            let expected: Vec<&str> = parts.iter().flat_map(|p| p.split_whitespace()).collect();
+            assert_eq!(words, expected);
+        }
+        assert_eq!(wrap(&parts, 200, 4).len(), 1);
+    }
+
+    #[test]
+    fn wrap_long_part() {
+        let parts = vec!["a".repeat(100), "b".to_string()];
+        assert_eq!(
+            wrap(&parts, 60, 2),
+            vec!["a".repeat(100), "  b".to_string()]
+        );
+    }
+}
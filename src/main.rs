@@ -1,14 +1,45 @@
+mod calllog;
+mod caps;
 mod dbg;
+mod disas;
+mod elf;
+mod events;
+mod expr;
+mod fpregs;
+mod hitlog;
+mod layout;
+mod output;
+mod repro;
+mod selftest;
+mod syscall;
 
 use dbg::{State, ZDbg};
-use rustyline::{error::ReadlineError, Editor};
-use std::{env, error::Error};
+use rustyline::{config::Configurer, error::ReadlineError, Editor};
+use std::{env, error::Error, path::PathBuf};
+
+/// 履歴ファイルに保存するコマンドの最大数
+const MAX_HISTORY: usize = 1000;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
+    if args.len() == 2 && args[1] == "--selftest" {
+        return selftest::run();
+    }
+    if args.get(1).map(|a| a.as_str()) == Some("--version") {
+        let caps = caps::Capabilities::probe();
+        match args.get(2).map(|a| a.as_str()) {
+            Some("--json") => println!("{}", caps.to_json()),
+            _ => caps.print(),
+        }
+        return Ok(());
+    }
+
     if args.len() < 2 {
-        let err: Box<dyn Error> =
-            format!("引数が必要です\n例：{} 実行ファイル [引数*]", args[0]).into();
+        let err: Box<dyn Error> = format!(
+            "引数が必要です\n例：{} 実行ファイル [引数*]\n環境の確認：{} --selftest\nバージョン：{} --version [--json]",
+            args[0], args[0], args[0]
+        )
+        .into();
         return Err(err);
     }
 
@@ -17,13 +48,43 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 fn run_dbg(filename: &str) -> Result<(), Box<dyn Error>> {
+    let mut rl = Editor::<()>::new();
+    rl.set_max_history_size(MAX_HISTORY);
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = rl.load_history(path); // 初回は履歴ファイルが存在しない
+    }
+
+    // エラーで終了する場合も履歴を保存
+    let ret = repl(&mut rl, filename);
+    if let Some(path) = &history {
+        if let Err(e) = rl.save_history(path) {
+            eprintln!("<<履歴を保存できません：{}：{e}>>", path.display());
+        }
+    }
+    ret
+}
+
+/// 履歴ファイルのパス (~/.zdbg_history)。HOMEが設定されていない場合はNone
+fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".zdbg_history"))
+}
+
+/// コマンドを読み込んで実行
+fn repl(rl: &mut Editor<()>, filename: &str) -> Result<(), Box<dyn Error>> {
+    println!(
+        "<<zdbg {} ({})。helpでコマンド一覧、info zdbgで利用可能な機能を表示>>",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::ARCH
+    );
     let debugger = ZDbg::new(filename.to_string());
     let mut state = State::NotRunning(debugger);
-    let mut rl = Editor::<()>::new();
 
     loop {
         match rl.readline("zdbg > ") {
             Ok(line) => {
+                rl.add_history_entry(line.as_str());
                 let trimed = line.trim(); // 行頭と行末の空白文字を削除
                 let cmd: Vec<&str> = trimed.split(' ').filter(|c| !c.is_empty()).collect(); // 空文字を削除
                 state = match state {
@@ -34,7 +95,6 @@ fn run_dbg(filename: &str) -> Result<(), Box<dyn Error>> {
                 if let State::Exit = state {
                     break;
                 }
-                rl.add_history_entry(line);
             }
             Err(ReadlineError::Interrupted) => eprintln!("<<終了はCtrl+D>>"),
             _ => {
@@ -0,0 +1,136 @@
+//! 子プロセスの標準エラー出力の監視 (stop-on-output)
+//!
+//! 子プロセスの標準エラー出力をパイプ経由でデバッガの標準エラー出力に転送し、
+//! 転送するスレッドで各行をパターンと照合する。
+//! 一致した場合は子プロセスにSIGSTOPを送り、なるべく原因に近い位置で停止させる。
+
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::io::{FromRawFd, RawFd},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+/// 監視するパターン
+struct Pattern {
+    text: String,   // 行に含まれるか調べる文字列
+    count: usize,   // count回目の一致で停止
+    matched: usize, // 一致した回数
+}
+
+/// 転送スレッドと共有する状態
+struct Inner {
+    patterns: Vec<Pattern>,
+    hit: Option<String>, // 停止の原因となった行。停止を報告したらNone
+}
+
+/// stop-on-outputの設定と、停止の原因となった行
+pub struct OutputWatch {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl OutputWatch {
+    pub fn new() -> Self {
+        OutputWatch {
+            inner: Arc::new(Mutex::new(Inner {
+                patterns: Vec::new(),
+                hit: None,
+            })),
+        }
+    }
+
+    /// パターンを追加。count回目の一致で停止
+    pub fn add(&self, text: &str, count: usize) {
+        self.inner.lock().unwrap().patterns.push(Pattern {
+            text: text.to_string(),
+            count,
+            matched: 0,
+        });
+    }
+
+    /// すべてのパターンを削除
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().patterns.clear();
+    }
+
+    /// パターンが設定されていないか
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().patterns.is_empty()
+    }
+
+    /// パターンの一覧を表示
+    pub fn print(&self) {
+        let inner = self.inner.lock().unwrap();
+        if inner.patterns.is_empty() {
+            println!("<<stop-on-outputのパターンはありません>>");
+            return;
+        }
+
+        for (i, p) in inner.patterns.iter().enumerate() {
+            println!(
+                "[{}] {:?}  count={}  matched={}",
+                i + 1,
+                p.text,
+                p.count,
+                p.matched
+            );
+        }
+    }
+
+    /// 停止の原因となった行を取得
+    pub fn take_hit(&self) -> Option<String> {
+        self.inner.lock().unwrap().hit.take()
+    }
+
+    /// 一致した回数をリセットし、fdから読み込んで転送するスレッドを開始
+    /// fdは子プロセスの標準エラー出力につながったパイプの読み込み側で、スレッドが閉じる
+    /// 子プロセスが終了してパイプが閉じられるとスレッドも終了する
+    pub fn spawn(&self, fd: RawFd, pid: Pid) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.hit = None;
+            for p in inner.patterns.iter_mut() {
+                p.matched = 0;
+            }
+        }
+
+        let inner = self.inner.clone();
+        let file = unsafe { File::from_raw_fd(fd) };
+        thread::spawn(move || {
+            let mut reader = BufReader::new(file);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => (),
+                }
+
+                let _ = io::stderr().write_all(&line);
+                check_line(&inner, &String::from_utf8_lossy(&line), pid);
+            }
+        });
+    }
+}
+
+/// 行をパターンと照合し、指定回数目の一致であれば子プロセスを停止
+fn check_line(inner: &Mutex<Inner>, line: &str, pid: Pid) {
+    let mut inner = inner.lock().unwrap();
+    let mut stop = false;
+    for p in inner.patterns.iter_mut() {
+        if p.matched < p.count && line.contains(&p.text) {
+            p.matched += 1;
+            stop |= p.matched == p.count;
+        }
+    }
+
+    if stop && inner.hit.is_none() {
+        inner.hit = Some(line.trim_end().to_string());
+        let _ = kill(pid, Signal::SIGSTOP);
+    }
+}
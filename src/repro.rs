@@ -0,0 +1,156 @@
+//! 再現性の確認 (repro-check)
+//!
+//! 同じ引数、同じ環境、ASLR無効の状態でターゲットを複数回実行し、
+//! 標準出力、標準エラー出力、終了ステータスが一致するかを比較する。
+
+use nix::sys::personality::{self, Persona};
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs::{self, File},
+    io::Read,
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// 比較する出力の最大サイズ (バイト)。これを超えた分は比較しない
+const MAX_CAPTURE: u64 = 1024 * 1024;
+
+/// 1回分の実行結果
+struct RunResult {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    status: String,
+    truncated: bool, // 出力がMAX_CAPTUREを超えたか
+}
+
+/// filenameをn回実行し、結果を比較して表示
+pub fn check(filename: &str, n: usize) -> Result<(), Box<dyn Error>> {
+    let dir = env::temp_dir().join(format!("zdbg-repro-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+
+    let results: Result<Vec<RunResult>, Box<dyn Error>> =
+        (0..n).map(|i| run_once(filename, &dir, i)).collect();
+    let _ = fs::remove_dir_all(&dir);
+    let results = results?;
+
+    if results.iter().any(|r| r.truncated) {
+        println!("<<出力が{MAX_CAPTURE}バイトを超えたため、超えた分は比較していません>>");
+    }
+
+    let mut same = true;
+    same &= report_stream("stdout", results.iter().map(|r| &r.stdout[..]));
+    same &= report_stream("stderr", results.iter().map(|r| &r.stderr[..]));
+
+    let mut statuses: BTreeMap<&str, usize> = BTreeMap::new();
+    for r in results.iter() {
+        *statuses.entry(&r.status).or_default() += 1;
+    }
+    if statuses.len() > 1 {
+        same = false;
+        println!("終了ステータス：");
+        for (status, count) in statuses.iter() {
+            println!("  {status}：{count}回");
+        }
+    }
+
+    if same {
+        println!("<<{n}回の実行結果はすべて同一です>>");
+    } else {
+        println!("<<{n}回の実行結果に差異があります>>");
+    }
+
+    Ok(())
+}
+
+/// 1回実行し、出力をdir内のファイルに保存してから読み込む
+fn run_once(filename: &str, dir: &Path, i: usize) -> Result<RunResult, Box<dyn Error>> {
+    let out_path = dir.join(format!("{i}.stdout"));
+    let err_path = dir.join(format!("{i}.stderr"));
+
+    let mut command = Command::new(filename);
+    command
+        .stdin(Stdio::null())
+        .stdout(File::create(&out_path)?)
+        .stderr(File::create(&err_path)?);
+    unsafe {
+        command.pre_exec(|| {
+            // runと同様にASLRを無効に
+            let p = personality::get()?;
+            personality::set(p | Persona::ADDR_NO_RANDOMIZE)?;
+            Ok(())
+        });
+    }
+
+    let status = command.status()?;
+    let (stdout, out_truncated) = read_capture(&out_path)?;
+    let (stderr, err_truncated) = read_capture(&err_path)?;
+
+    Ok(RunResult {
+        stdout,
+        stderr,
+        status: status_str(status),
+        truncated: out_truncated || err_truncated,
+    })
+}
+
+/// 保存した出力を最大MAX_CAPTUREバイト読み込む。超えた場合はtrueも返す
+fn read_capture(path: &Path) -> Result<(Vec<u8>, bool), Box<dyn Error>> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut buf = Vec::new();
+    file.take(MAX_CAPTURE).read_to_end(&mut buf)?;
+    Ok((buf, len > MAX_CAPTURE))
+}
+
+/// 終了ステータスを文字列に変換
+fn status_str(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exit {code}"),
+        (None, Some(sig)) => format!("signal {sig}"),
+        _ => "unknown".into(),
+    }
+}
+
+/// 1回目と異なる出力があれば、最初に異なる実行の異なる位置を表示。すべて一致した場合はtrue
+fn report_stream<'a>(name: &str, mut outputs: impl Iterator<Item = &'a [u8]>) -> bool {
+    let first = match outputs.next() {
+        Some(first) => first,
+        None => return true,
+    };
+
+    for (i, out) in outputs.enumerate() {
+        let offset = match first.iter().zip(out).position(|(a, b)| a != b) {
+            Some(offset) => offset,
+            None if first.len() == out.len() => continue,
+            None => first.len().min(out.len()),
+        };
+
+        let line = first[..offset].iter().filter(|&&b| b == b'\n').count() + 1;
+        println!(
+            "{name}：1回目と{}回目が異なります (行 {line}、オフセット {offset})",
+            i + 2
+        );
+        println!("  1回目：{}", line_at(first, offset));
+        println!("  {}回目：{}", i + 2, line_at(out, offset));
+        return false;
+    }
+
+    true
+}
+
+/// offsetを含む行を取得
+fn line_at(buf: &[u8], offset: usize) -> String {
+    let offset = offset.min(buf.len());
+    let start = buf[..offset]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |p| p + 1);
+    let end = buf[offset..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(buf.len(), |p| offset + p);
+    format!("{:?}", String::from_utf8_lossy(&buf[start..end]))
+}
@@ -0,0 +1,296 @@
+//! 実行環境のセルフテスト (zdbg --selftest)
+//!
+//! 小さな子プロセスを生成してトレースし、デバッガが利用するptraceの機能が
+//! この環境で使えるかを確認する。
+
+use nix::{
+    libc,
+    sys::{
+        personality::{self, Persona},
+        ptrace,
+        signal::Signal,
+        uio::{process_vm_readv, RemoteIoVec},
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::{execv, fork, ForkResult, Pid},
+};
+use std::{
+    env,
+    error::Error,
+    ffi::{c_void, CString},
+    fs,
+    io::IoSliceMut,
+};
+
+/// テスト結果
+struct Check {
+    name: &'static str,
+    required: bool,             // 失敗した場合にセルフテスト全体を失敗とするか
+    result: Result<(), String>, // 失敗した場合は理由
+    hint: &'static str,         // 失敗した場合の対処法
+}
+
+/// セルフテストを実行し、必須項目がすべて成功した場合はOkを返す
+pub fn run() -> Result<(), Box<dyn Error>> {
+    let mut checks = vec![check_ptrace_scope()];
+
+    match spawn_traced() {
+        Ok(child) => {
+            checks.push(Check {
+                name: "fork + PTRACE_TRACEME + exec",
+                required: true,
+                result: Ok(()),
+                hint: "",
+            });
+            checks.push(check_peek_poke(child));
+            checks.push(check_no_randomize(child));
+            checks.push(check_process_vm_readv(child));
+            checks.push(check_debug_regs(child));
+            kill_child(child);
+        }
+        Err(e) => checks.push(Check {
+            name: "fork + PTRACE_TRACEME + exec",
+            required: true,
+            result: Err(e),
+            hint: "コンテナ内の場合はseccompでptraceが禁止されていないか確認してください (例：--cap-add=SYS_PTRACE)",
+        }),
+    }
+
+    let mut failed = false;
+    for c in checks.iter() {
+        match &c.result {
+            Ok(()) => println!("[PASS] {}", c.name),
+            Err(e) => {
+                let level = if c.required { "FAIL" } else { "WARN" };
+                println!("[{level}] {}：{e}", c.name);
+                println!("       {}", c.hint);
+                failed |= c.required;
+            }
+        }
+    }
+
+    if failed {
+        Err("必須の機能が利用できません".into())
+    } else {
+        Ok(())
+    }
+}
+
+/// 自身の実行ファイルをトレース対象として起動し、exec直後で停止させる
+fn spawn_traced() -> Result<Pid, String> {
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    let exe = CString::new(exe.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+
+    match unsafe { fork() }.map_err(|e| e.to_string())? {
+        ForkResult::Child => {
+            if let Ok(p) = personality::get() {
+                let _ = personality::set(p | Persona::ADDR_NO_RANDOMIZE);
+            }
+            if ptrace::traceme().is_err() {
+                unsafe { libc::_exit(1) };
+            }
+            let _ = execv(&exe, &[&exe]);
+            unsafe { libc::_exit(1) };
+        }
+        ForkResult::Parent { child } => match waitpid(child, None).map_err(|e| e.to_string())? {
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => Ok(child),
+            status => Err(format!("exec直後に停止しませんでした：{status:?}")),
+        },
+    }
+}
+
+/// 子プロセスをkillして回収
+fn kill_child(child: Pid) {
+    let _ = ptrace::kill(child);
+    let _ = waitpid(child, None);
+}
+
+/// PEEKTEXT/POKETEXTでメモリを書き換えて読み戻せるか
+fn check_peek_poke(child: Pid) -> Check {
+    let result = (|| {
+        let addr = ptrace::getregs(child).map_err(|e| e.to_string())?.rip as *mut c_void;
+        let orig = ptrace::read(child, addr).map_err(|e| e.to_string())?;
+        let patched = (orig & !0xff) | 0xcc;
+        unsafe { ptrace::write(child, addr, patched as *mut c_void) }.map_err(|e| e.to_string())?;
+        let val = ptrace::read(child, addr).map_err(|e| e.to_string())?;
+        unsafe { ptrace::write(child, addr, orig as *mut c_void) }.map_err(|e| e.to_string())?;
+
+        if val == patched {
+            Ok(())
+        } else {
+            Err(format!("書き込んだ値と異なります：{val:#x}"))
+        }
+    })();
+
+    Check {
+        name: "PEEKTEXT/POKETEXT",
+        required: true,
+        result,
+        hint: "ブレークポイントが設定できません。ptraceが制限されていないか確認してください",
+    }
+}
+
+/// ADDR_NO_RANDOMIZEが子プロセスに反映されているか
+fn check_no_randomize(child: Pid) -> Check {
+    let result = fs::read_to_string(format!("/proc/{child}/personality"))
+        .map_err(|e| e.to_string())
+        .and_then(|p| u64::from_str_radix(p.trim(), 16).map_err(|e| e.to_string()))
+        .and_then(|p| {
+            if p & Persona::ADDR_NO_RANDOMIZE.bits() as u64 != 0 {
+                Ok(())
+            } else {
+                Err("ADDR_NO_RANDOMIZEが設定されていません".into())
+            }
+        });
+
+    Check {
+        name: "ASLRの無効化 (ADDR_NO_RANDOMIZE)",
+        required: false,
+        result,
+        hint: "実行ごとにアドレスが変わります。seccompでpersonalityが禁止されていないか確認してください",
+    }
+}
+
+/// process_vm_readvで子プロセスのメモリを読めるか
+fn check_process_vm_readv(child: Pid) -> Check {
+    let result = (|| {
+        let rsp = ptrace::getregs(child).map_err(|e| e.to_string())?.rsp;
+        let mut buf = [0u8; 8];
+        let remote = [RemoteIoVec {
+            base: rsp as usize,
+            len: buf.len(),
+        }];
+        process_vm_readv(child, &mut [IoSliceMut::new(&mut buf)], &remote)
+            .map(drop)
+            .map_err(|e| e.to_string())
+    })();
+
+    Check {
+        name: "process_vm_readv",
+        required: false,
+        result,
+        hint: "メモリの読み込みはPEEKDATAで行われるため低速になります",
+    }
+}
+
+/// 子プロセスでASLRが無効になっているか (info zdbg)
+pub fn no_randomize(child: Pid) -> bool {
+    check_no_randomize(child).result.is_ok()
+}
+
+/// 子プロセスのメモリをprocess_vm_readvで読めるか (info zdbg)
+pub fn vm_readv(child: Pid) -> bool {
+    check_process_vm_readv(child).result.is_ok()
+}
+
+/// ハードウェアデバッグレジスタ (DR0) に書き込めるか
+fn check_debug_regs(child: Pid) -> Check {
+    let offset = std::mem::offset_of!(libc::user, u_debugreg);
+    let result = (|| {
+        let addr = ptrace::getregs(child).map_err(|e| e.to_string())?.rip;
+        let ret = unsafe { libc::ptrace(libc::PTRACE_POKEUSER, child.as_raw(), offset, addr) };
+        if ret < 0 {
+            return Err(nix::errno::Errno::last().to_string());
+        }
+
+        let val = unsafe { libc::ptrace(libc::PTRACE_PEEKUSER, child.as_raw(), offset, 0) };
+        unsafe { libc::ptrace(libc::PTRACE_POKEUSER, child.as_raw(), offset, 0) };
+        if val as u64 == addr {
+            Ok(())
+        } else {
+            Err(format!("書き込んだ値と異なります：{val:#x}"))
+        }
+    })();
+
+    Check {
+        name: "ハードウェアデバッグレジスタ",
+        required: false,
+        result,
+        hint: "仮想環境によってはデバッグレジスタが利用できません",
+    }
+}
+
+/// yamaのptrace_scopeによりattachが制限されているか
+fn check_ptrace_scope() -> Check {
+    let result = match fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope") {
+        Ok(s) if s.trim() == "0" => Ok(()),
+        Ok(s) => Err(format!("ptrace_scope = {}", s.trim())),
+        Err(_) => Ok(()), // yamaが無効
+    };
+
+    Check {
+        name: "yama ptrace_scope",
+        required: false,
+        result,
+        hint: "実行中のプロセスへのattachが制限されます (sysctl kernel.yama.ptrace_scope=0 で解除)",
+    }
+}
+
+/// 実行環境の診断結果と、制限される機能を表示
+/// ptraceの失敗など、デバッガが想定どおりに動作しない場合に原因の調査に用いる
+pub fn print_diagnosis(degraded: &[&str]) {
+    let status = fs::read_to_string("/proc/self/status").ok();
+    let field = |key: &str| -> Option<String> {
+        status.as_ref()?.lines().find_map(|l| {
+            l.strip_prefix(key)
+                .and_then(|v| v.strip_prefix(':'))
+                .map(|v| v.trim().to_string())
+        })
+    };
+
+    println!("<<実行環境の診断>>");
+    match detect_container() {
+        Some(c) => println!("  コンテナ：{c}"),
+        None => println!("  コンテナ：検出されませんでした"),
+    }
+
+    if status.is_none() {
+        println!("  /proc：マウントされていません");
+    } else {
+        let seccomp = match field("Seccomp").as_deref() {
+            Some("0") => "無効".to_string(),
+            Some("1") => "strictモード".to_string(),
+            Some("2") => format!(
+                "フィルタモード (フィルタ数 {})",
+                field("Seccomp_filters").unwrap_or_else(|| "不明".into())
+            ),
+            _ => "不明".to_string(),
+        };
+        println!("  seccomp：{seccomp}");
+
+        match field("TracerPid").as_deref() {
+            Some("0") | None => (),
+            Some(pid) => println!("  トレーサ：PID {pid} (zdbg自身がトレースされています)"),
+        }
+        if field("NoNewPrivs").as_deref() == Some("1") {
+            println!("  NoNewPrivs：有効");
+        }
+    }
+
+    if let Ok(s) = fs::read_to_string("/proc/sys/kernel/yama/ptrace_scope") {
+        println!("  ptrace_scope：{}", s.trim());
+    }
+
+    if !degraded.is_empty() {
+        println!("制限される機能：");
+        for d in degraded {
+            println!("  - {d}");
+        }
+    }
+}
+
+/// コンテナランタイムを検出
+fn detect_container() -> Option<String> {
+    if fs::metadata("/.dockerenv").is_ok() {
+        return Some("docker (/.dockerenv)".into());
+    }
+    if fs::metadata("/run/.containerenv").is_ok() {
+        return Some("podman (/run/.containerenv)".into());
+    }
+
+    let cgroup = fs::read_to_string("/proc/1/cgroup").ok()?;
+    ["docker", "kubepods", "containerd", "libpod", "lxc"]
+        .iter()
+        .find(|k| cgroup.contains(*k))
+        .map(|k| format!("{k} (/proc/1/cgroup)"))
+}
@@ -0,0 +1,95 @@
+//! x86-64 Linuxのシステムコール番号と名前 (strace)
+
+/// システムコール番号から名前を取得。主なもののみ対応
+pub fn name(nr: u64) -> Option<&'static str> {
+    let name = match nr {
+        0 => "read",
+        1 => "write",
+        2 => "open",
+        3 => "close",
+        4 => "stat",
+        5 => "fstat",
+        6 => "lstat",
+        7 => "poll",
+        8 => "lseek",
+        9 => "mmap",
+        10 => "mprotect",
+        11 => "munmap",
+        12 => "brk",
+        13 => "rt_sigaction",
+        14 => "rt_sigprocmask",
+        15 => "rt_sigreturn",
+        16 => "ioctl",
+        17 => "pread64",
+        18 => "pwrite64",
+        19 => "readv",
+        20 => "writev",
+        21 => "access",
+        22 => "pipe",
+        23 => "select",
+        24 => "sched_yield",
+        25 => "mremap",
+        28 => "madvise",
+        32 => "dup",
+        33 => "dup2",
+        35 => "nanosleep",
+        39 => "getpid",
+        41 => "socket",
+        42 => "connect",
+        43 => "accept",
+        44 => "sendto",
+        45 => "recvfrom",
+        49 => "bind",
+        50 => "listen",
+        56 => "clone",
+        57 => "fork",
+        58 => "vfork",
+        59 => "execve",
+        60 => "exit",
+        61 => "wait4",
+        62 => "kill",
+        63 => "uname",
+        72 => "fcntl",
+        78 => "getdents",
+        79 => "getcwd",
+        80 => "chdir",
+        82 => "rename",
+        83 => "mkdir",
+        84 => "rmdir",
+        87 => "unlink",
+        89 => "readlink",
+        96 => "gettimeofday",
+        97 => "getrlimit",
+        102 => "getuid",
+        104 => "getgid",
+        107 => "geteuid",
+        108 => "getegid",
+        110 => "getppid",
+        131 => "sigaltstack",
+        158 => "arch_prctl",
+        186 => "gettid",
+        202 => "futex",
+        217 => "getdents64",
+        218 => "set_tid_address",
+        228 => "clock_gettime",
+        230 => "clock_nanosleep",
+        231 => "exit_group",
+        232 => "epoll_wait",
+        233 => "epoll_ctl",
+        257 => "openat",
+        262 => "newfstatat",
+        270 => "pselect6",
+        271 => "ppoll",
+        273 => "set_robust_list",
+        281 => "epoll_pwait",
+        290 => "eventfd2",
+        291 => "epoll_create1",
+        293 => "pipe2",
+        302 => "prlimit64",
+        318 => "getrandom",
+        334 => "rseq",
+        435 => "clone3",
+        _ => return None,
+    };
+    Some(name)
+}
@@ -0,0 +1,34 @@
+//! backtraceとフレームポインタ
+
+mod common;
+
+#[test]
+fn garbage_rbp_does_not_crash() {
+    // フレームポインタを使用しないコードでは、RBPに任意の値が入り得る
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break g",
+            "run",
+            "setreg rbp 0xfffffffffffffff8",
+            "backtrace",
+        ],
+    );
+
+    assert!(out.contains("#0  0x"), "{out}");
+    assert!(!out.contains("panicked"), "{out}");
+    assert!(
+        out.contains("<<子プロセスが停止しました：SIGTRAP>>"),
+        "{out}"
+    );
+}
+
+#[test]
+fn frames_from_g_to_main() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break g", "run", "backtrace"]);
+
+    assert!(out.contains("<g>"), "{out}");
+    assert!(out.contains("<main+0x"), "{out}");
+}
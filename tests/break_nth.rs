@@ -0,0 +1,40 @@
+//! break --nthと--nth-from-now
+
+mod common;
+
+#[test]
+fn nth_stops_at_nth_hit() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break g --nth 3", "run", "calc $rdi"]);
+
+    assert!(
+        out.contains("ブレークポイント 1 の3回目のヒットで停止しました：hits = 3"),
+        "{out}"
+    );
+    assert!(out.contains("$1 = 0x2"), "{out}");
+}
+
+#[test]
+fn nth_from_now_counts_from_current_hits() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break g --nth 3",
+            "run",
+            "break g --nth-from-now 4",
+            "continue",
+            "calc $rdi",
+        ],
+    );
+
+    assert!(
+        out.contains("ブレークポイント 1 は今から4回目のヒットで停止します"),
+        "{out}"
+    );
+    assert!(
+        out.contains("ブレークポイント 1 の4回目のヒットで停止しました：hits = 7"),
+        "{out}"
+    );
+    assert!(out.contains("$1 = 0x6"), "{out}");
+}
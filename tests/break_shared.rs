@@ -0,0 +1,54 @@
+//! 内部用のブレークポイントと同じアドレスへのbreak
+
+mod common;
+
+#[test]
+fn break_at_internal_breakpoint() {
+    // _dl_debug_stateには共有ライブラリの読み込みを検出する内部用のブレークポイントがある
+    let exe = common::fixture("dlopen", &["-ldl"]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "set event library log",
+            "break main",
+            "run",
+            "break _dl_debug_state",
+            "info break",
+            "continue",
+            "delete 2",
+            "continue",
+        ],
+    );
+
+    assert!(!out.contains("は設定済みです"), "{out}");
+    assert!(out.contains("<<ブレークポイント 2 を設定しました"), "{out}");
+    let line = out
+        .lines()
+        .find(|l| l.contains("<_dl_debug_state>"))
+        .unwrap_or_else(|| panic!("{out}"));
+    assert!(
+        line.contains("armed") && !line.contains("not armed"),
+        "{out}"
+    );
+
+    // ユーザのブレークポイントで1回停止し、削除後も内部用のブレークポイントでlibrt.so.1を検出する
+    assert_eq!(
+        out.matches("<<子プロセスが停止しました：SIGTRAP>>").count(),
+        2,
+        "{out}"
+    );
+    let stop = out
+        .rfind("<<子プロセスが停止しました：SIGTRAP>>")
+        .unwrap_or_else(|| panic!("{out}"));
+    let librt = out.find("librt.so.1>>").unwrap_or_else(|| panic!("{out}"));
+    assert!(stop < librt, "{out}");
+    assert!(out.contains("loaded=1"), "{out}");
+}
+
+#[test]
+fn break_twice_is_rejected() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break g", "break g"]);
+
+    assert!(out.contains("<<ブレークポイント 1 は設定済みです"), "{out}");
+}
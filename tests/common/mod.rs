@@ -0,0 +1,62 @@
+//! 統合テストの共通処理
+//!
+//! tests/fixtures/のCのプログラムをccでビルドし、zdbgの標準入力にコマンドを与えて実行する。
+
+#![allow(dead_code)]
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// 並列に実行されるテストでビルドした実行ファイルが衝突しないよう、出力先に付ける番号
+static BUILD_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// tests/fixtures/<name>.cをビルドし、実行ファイルのパスを返す。argsはccに追加で渡す引数
+pub fn fixture(name: &str, args: &[&str]) -> PathBuf {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(format!("{name}.c"));
+    let id = BUILD_ID.fetch_add(1, Ordering::Relaxed);
+    let out =
+        Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}-{}-{id}", std::process::id()));
+
+    let status = Command::new("cc")
+        .args(["-g", "-O0", "-fno-omit-frame-pointer", "-o"])
+        .arg(&out)
+        .arg(&src)
+        .args(args)
+        .status()
+        .expect("ccを実行できません");
+    assert!(status.success(), "{}をビルドできません", src.display());
+    out
+}
+
+/// exeを対象にzdbgを起動し、cmdsを1行ずつ入力して、標準出力と標準エラー出力を返す
+/// 最後にexitを入力する。履歴ファイルはCARGO_TARGET_TMPDIRに作成する
+pub fn zdbg(exe: &Path, cmds: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_zdbg"))
+        .arg(exe)
+        .env("HOME", env!("CARGO_TARGET_TMPDIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("zdbgを実行できません");
+
+    let mut input = cmds.join("\n");
+    input.push_str("\nexit\n");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let out = child.wait_with_output().unwrap();
+    let mut s = String::from_utf8_lossy(&out.stdout).into_owned();
+    s.push_str(&String::from_utf8_lossy(&out.stderr));
+    s
+}
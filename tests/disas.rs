@@ -0,0 +1,27 @@
+//! 読み込めないメモリの逆アセンブル
+
+mod common;
+
+#[test]
+fn unreadable_words_are_shown() {
+    // 0x10はマッピングされていないため、3命令分の3ワードをすべて??で表示する
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break main", "run", "disas 0x10 3"]);
+
+    let unknown = ["??"; 8].join(" ");
+    for a in ["0x10", "0x18", "0x20"] {
+        assert!(out.contains(&format!("   {a}:  {unknown}\n")), "{out}");
+    }
+    assert!(!out.contains("0x28:"), "{out}");
+    assert!(out.contains("<<0x10を読み込めません："), "{out}");
+}
+
+#[test]
+fn unreadable_partial_word() {
+    // 0x14から読む場合、最初のワードは0x18までの4バイト
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break main", "run", "disas 0x14 2"]);
+
+    assert!(out.contains("   0x14:  ?? ?? ?? ??\n"), "{out}");
+    assert!(out.contains("   0x18:  ?? ?? ?? ?? ?? ?? ?? ??\n"), "{out}");
+}
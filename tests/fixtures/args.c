@@ -0,0 +1,9 @@
+/* コマンドライン引数をすべて表示 */
+#include <stdio.h>
+
+int main(int argc, char **argv) {
+    for (int i = 0; i < argc; i++) {
+        printf("argv[%d]=%s\n", i, argv[i]);
+    }
+    return 0;
+}
@@ -0,0 +1,9 @@
+/* gを引数0〜9で10回呼び出す */
+void g(int i) {}
+
+int main(void) {
+    for (int i = 0; i < 10; i++) {
+        g(i);
+    }
+    return 0;
+}
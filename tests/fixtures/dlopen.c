@@ -0,0 +1,10 @@
+/* libm.so.6とlibrt.so.1をdlopenで順に読み込む */
+#include <dlfcn.h>
+#include <stdio.h>
+
+int main(void) {
+    void *m = dlopen("libm.so.6", RTLD_NOW);
+    void *rt = dlopen("librt.so.1", RTLD_NOW);
+    printf("loaded=%d\n", m != NULL && rt != NULL);
+    return 0;
+}
@@ -0,0 +1,12 @@
+/* /dev/nullを閉じずに20回開き、開くたびにcheckpointを呼び出す */
+#include <fcntl.h>
+
+void checkpoint(int n) {}
+
+int main(void) {
+    for (int i = 1; i <= 20; i++) {
+        open("/dev/null", O_RDONLY);
+        checkpoint(i);
+    }
+    return 0;
+}
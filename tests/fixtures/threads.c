@@ -0,0 +1,33 @@
+/* スレッドを3つ生成してcheckpointを呼び出し、すべて終了させてから再度checkpointを呼び出す */
+#include <pthread.h>
+#include <unistd.h>
+
+#define NTHREADS 3
+
+static int fds[2];
+
+void checkpoint(int n) {}
+
+/* パイプが閉じられるまで待機 */
+static void *worker(void *arg) {
+    char c;
+    while (read(fds[0], &c, 1) > 0) {
+    }
+    return NULL;
+}
+
+int main(void) {
+    pthread_t th[NTHREADS];
+    pipe(fds);
+    for (int i = 0; i < NTHREADS; i++) {
+        pthread_create(&th[i], NULL, worker, NULL);
+    }
+    checkpoint(1);
+
+    close(fds[1]);
+    for (int i = 0; i < NTHREADS; i++) {
+        pthread_join(th[i], NULL);
+    }
+    checkpoint(2);
+    return 0;
+}
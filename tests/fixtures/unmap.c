@@ -0,0 +1,14 @@
+/* 0x10000000に1ページをマッピングしてcheckpointを呼び出し、解放してから再度checkpointを呼び出す */
+#include <sys/mman.h>
+
+#define PAGE ((void *)0x10000000)
+
+void checkpoint(int n) {}
+
+int main(void) {
+    mmap(PAGE, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED, -1, 0);
+    checkpoint(1);
+    munmap(PAGE, 4096);
+    checkpoint(2);
+    return 0;
+}
@@ -0,0 +1,47 @@
+//! 共有ライブラリの読み込み (libraryイベント)
+
+mod common;
+
+#[test]
+fn library_stop_on_dlopen() {
+    let exe = common::fixture("dlopen", &["-ldl"]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "set event library stop",
+            "run",
+            "continue",
+            "continue",
+            "continue",
+        ],
+    );
+
+    // libc、libm、librtの順に、それぞれ停止して表示する
+    let libc = out
+        .find("<<共有ライブラリを読み込みました：")
+        .unwrap_or_else(|| panic!("{out}"));
+    let libm = out.find("libm.so.6>>").unwrap_or_else(|| panic!("{out}"));
+    let librt = out.find("librt.so.1>>").unwrap_or_else(|| panic!("{out}"));
+    assert!(libc < libm && libm < librt, "{out}");
+    assert_eq!(
+        out.matches("<<子プロセスが停止しました：SIGTRAP>>").count(),
+        3,
+        "{out}"
+    );
+    assert!(out.contains("loaded=1"), "{out}");
+    assert!(!out.contains("ld.so.cache"), "{out}");
+}
+
+#[test]
+fn library_ignored_by_default() {
+    let exe = common::fixture("dlopen", &["-ldl"]);
+    let out = common::zdbg(&exe, &["run", "events recent"]);
+
+    assert!(!out.contains("<<共有ライブラリを読み込みました"), "{out}");
+    assert!(out.contains("loaded=1"), "{out}");
+    assert!(
+        out.lines()
+            .any(|l| l.contains("library") && l.contains("ignore") && l.contains("libm.so.6")),
+        "{out}"
+    );
+}
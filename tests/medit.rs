@@ -0,0 +1,30 @@
+//! meditの範囲外の編集
+
+mod common;
+
+#[test]
+fn huge_offset_is_out_of_range() {
+    let exe = common::fixture("unmap", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break checkpoint",
+            "run",
+            "medit 0x10000000 4",
+            "0xffffffffffffffff=1",
+            "0=0x41",
+            "w",
+            "x/1x 0x10000000",
+        ],
+    );
+
+    assert!(
+        out.contains("<<範囲外です：0xffffffffffffffff=1 (長さ 4)>>"),
+        "{out}"
+    );
+    assert!(
+        out.contains("<<0x10000000から1バイトを書き込みました>>"),
+        "{out}"
+    );
+    assert!(out.contains("$1 = 0x41"), "{out}");
+}
@@ -0,0 +1,33 @@
+//! memory writeの不正なアドレス
+
+mod common;
+
+#[test]
+fn end_of_address_space() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break main",
+            "run",
+            "memory write 0xfffffffffffffffc 1",
+            "continue",
+        ],
+    );
+
+    assert!(
+        out.contains(
+            "<<0xfffffffffffffffcに書き込めません：書き込む範囲がアドレス空間を超えています"
+        ),
+        "{out}"
+    );
+    assert!(out.contains("<<子プロセスが終了しました>>"), "{out}");
+}
+
+#[test]
+fn unmapped_address() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break main", "run", "memory write 0x10 1"]);
+
+    assert!(out.contains("<<0x10に書き込めません："), "{out}");
+}
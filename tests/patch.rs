@@ -0,0 +1,65 @@
+//! patchとpatch undo
+
+mod common;
+
+#[test]
+fn patch_unmapped_keeps_running() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break main", "run", "patch 0x10 nop 1", "continue"]);
+
+    assert!(out.contains("<<0x10を読み込めません："), "{out}");
+    assert!(out.contains("<<子プロセスが終了しました>>"), "{out}");
+}
+
+#[test]
+fn undo_unmapped_keeps_patch() {
+    let exe = common::fixture("unmap", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break checkpoint",
+            "run",
+            "patch 0x10000000 bytes 41 42",
+            "continue",
+            "patch undo 1",
+            "info patches",
+            "continue",
+        ],
+    );
+
+    assert!(
+        out.contains("<<パッチ 1 を適用しました：0x10000000"),
+        "{out}"
+    );
+    assert!(out.contains("<<0x10000000に書き込めません："), "{out}");
+    assert!(!out.contains("<<パッチ 1 を元に戻しました"), "{out}");
+    assert!(!out.contains("<<パッチはありません>>"), "{out}");
+    assert!(out.contains("<<子プロセスが終了しました>>"), "{out}");
+}
+
+#[test]
+fn info_break_shows_patch() {
+    let exe = common::fixture("unmap", &[]);
+    let out = common::zdbg(
+        &exe,
+        &[
+            "break checkpoint",
+            "run",
+            "break 0x10000001",
+            "patch 0x10000000 bytes 41 42",
+            "info break",
+            "patch undo 1",
+            "info break",
+        ],
+    );
+
+    // パッチで書き換えたバイトにある0xccは残り、元に戻すと表示も消える
+    let lines: Vec<&str> = out.lines().filter(|l| l.starts_with("[2]")).collect();
+    assert_eq!(lines.len(), 2, "{out}");
+    assert!(lines[0].contains("patched=1"), "{out}");
+    assert!(
+        lines[0].contains("armed") && !lines[0].contains("not armed"),
+        "{out}"
+    );
+    assert!(!lines[1].contains("patched"), "{out}");
+}
@@ -0,0 +1,29 @@
+//! runで子プロセスに渡すコマンドライン引数
+
+mod common;
+
+#[test]
+fn argv0_is_executable() {
+    let exe = common::fixture("args", &[]);
+    let out = common::zdbg(&exe, &["run a b"]);
+
+    assert!(
+        out.contains(&format!("argv[0]={}\n", exe.display())),
+        "{out}"
+    );
+    assert!(!out.contains("argv[0]=run"), "{out}");
+    assert!(out.contains("argv[1]=a\nargv[2]=b\n"), "{out}");
+}
+
+#[test]
+fn argv0_on_rerun() {
+    // 終了後に再度runした場合も、argv[0]は実行ファイル名
+    let exe = common::fixture("args", &[]);
+    let out = common::zdbg(&exe, &["run x", "run"]);
+
+    assert_eq!(
+        out.matches(&format!("argv[0]={}\n", exe.display())).count(),
+        2,
+        "{out}"
+    );
+}
@@ -0,0 +1,31 @@
+//! set_breakによる0xccの書き込み
+
+mod common;
+
+#[test]
+fn set_break_writes_int3() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break main", "break g", "run", "info break"]);
+
+    // mainで停止した時点で、まだ到達していないgには0xccが書き込まれている
+    let line = out
+        .lines()
+        .find(|l| l.contains("<g>"))
+        .unwrap_or_else(|| panic!("{out}"));
+    assert!(
+        line.contains("armed") && !line.contains("not armed"),
+        "{out}"
+    );
+}
+
+#[test]
+fn set_break_stops_at_int3() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["break g", "run", "calc $rdi"]);
+
+    assert!(
+        out.contains("<<子プロセスが停止しました：SIGTRAP>>"),
+        "{out}"
+    );
+    assert!(out.contains("$1 = 0x0"), "{out}");
+}
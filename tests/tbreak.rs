@@ -0,0 +1,30 @@
+//! tbreakによる一時ブレークポイント
+
+mod common;
+
+#[test]
+fn tbreak_stops_only_once() {
+    // gは10回呼び出されるが、停止するのは最初の1回のみ
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["tbreak g", "run", "calc $rdi", "continue"]);
+
+    assert_eq!(
+        out.matches("<<子プロセスが停止しました：SIGTRAP>>").count(),
+        1,
+        "{out}"
+    );
+    assert!(out.contains("$1 = 0x0"), "{out}");
+    assert!(
+        out.contains("<<一時ブレークポイント 1 を削除しました"),
+        "{out}"
+    );
+    assert!(out.contains("<<子プロセスが終了しました>>"), "{out}");
+}
+
+#[test]
+fn tbreak_is_removed_after_hit() {
+    let exe = common::fixture("count", &[]);
+    let out = common::zdbg(&exe, &["tbreak g", "run", "info break"]);
+
+    assert!(out.contains("<<ブレークポイントはありません>>"), "{out}");
+}
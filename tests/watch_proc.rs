@@ -0,0 +1,59 @@
+//! watch-procによる/procの値の監視
+
+mod common;
+
+/// break checkpointで停止し、continueをn回実行するコマンド列
+fn run_checkpoints(watches: &[&str], n: usize) -> Vec<String> {
+    let mut cmds: Vec<String> = watches.iter().map(|w| format!("watch-proc {w}")).collect();
+    cmds.push("break checkpoint".into());
+    cmds.push("run".into());
+    cmds.extend(std::iter::repeat_n("continue".to_string(), n));
+    cmds
+}
+
+#[test]
+fn fd_count_triggers_once() {
+    let exe = common::fixture("fdleak", &[]);
+    let cmds = run_checkpoints(&["fd-count > 10"], 20);
+    let cmds: Vec<&str> = cmds.iter().map(String::as_str).collect();
+    let out = common::zdbg(&exe, &cmds);
+
+    // 標準入出力の3つに加え、8回目のopenで11個になる
+    assert_eq!(
+        out.matches("<<watch-procの条件が成立しました：").count(),
+        1,
+        "{out}"
+    );
+    assert!(
+        out.contains("<<watch-procの条件が成立しました：fd-count = 11 (> 10)>>"),
+        "{out}"
+    );
+}
+
+#[test]
+fn fd_count_below_threshold() {
+    let exe = common::fixture("fdleak", &[]);
+    let cmds = run_checkpoints(&["fd-count > 100"], 20);
+    let cmds: Vec<&str> = cmds.iter().map(String::as_str).collect();
+    let out = common::zdbg(&exe, &cmds);
+
+    assert!(out.contains("<<子プロセスが終了しました>>"), "{out}");
+    assert!(!out.contains("<<watch-procの条件が成立しました"), "{out}");
+}
+
+#[test]
+fn threads_spawned_and_joined() {
+    let exe = common::fixture("threads", &["-pthread"]);
+    let cmds = run_checkpoints(&["threads >= 4", "threads == 1"], 2);
+    let cmds: Vec<&str> = cmds.iter().map(String::as_str).collect();
+    let out = common::zdbg(&exe, &cmds);
+
+    // 1回目のcheckpointではメインスレッドと3つのスレッド、2回目ではメインスレッドのみ
+    let spawned = out
+        .find("<<watch-procの条件が成立しました：threads = 4 (>= 4)>>")
+        .unwrap_or_else(|| panic!("{out}"));
+    let joined = out
+        .find("<<watch-procの条件が成立しました：threads = 1 (== 1)>>")
+        .unwrap_or_else(|| panic!("{out}"));
+    assert!(spawned < joined, "{out}");
+}